@@ -0,0 +1,199 @@
+//! Async request engine.
+//!
+//! Executes the plan on a tokio runtime with shared, pooled hyper clients; bounding
+//! in-flight requests to '--pool-size' with a semaphore rather than one OS thread each.
+
+use crate::client;
+use crate::config::Config;
+use crate::errors::ClientError;
+use crate::plan::Plan;
+use crate::random;
+use crate::state;
+use crate::threadpool;
+
+use std::sync;
+use std::sync::atomic;
+use std::time;
+
+/// Http clients for the async engine. Each client holds its own connection pool, so with
+/// '--max-streams' HTTP/2 requests are spread over enough clients to keep each connection
+/// at or under the stream limit.
+struct Clients {
+    clients: Vec<(
+        client::HttpClient,
+        Option<sync::Arc<tokio::sync::Semaphore>>,
+    )>,
+    next: atomic::AtomicUsize,
+}
+
+impl Clients {
+    fn new(config: &Config, pool_size: usize) -> Result<Clients, ClientError> {
+        let mut clients = vec![];
+        if config.http_version == "1.1" || config.max_streams == 0 {
+            clients.push((client::http_client(config)?, None));
+        } else {
+            for _ in 0..pool_size.div_ceil(config.max_streams) {
+                let streams = tokio::sync::Semaphore::new(config.max_streams);
+                clients.push((client::http_client(config)?, Some(sync::Arc::new(streams))));
+            }
+        }
+
+        return Ok(Clients {
+            clients,
+            next: atomic::AtomicUsize::new(0),
+        });
+    }
+
+    /// Picks the next client with a free stream, round robin.
+    async fn acquire(
+        &self,
+    ) -> Result<
+        (
+            client::HttpClient,
+            Option<tokio::sync::OwnedSemaphorePermit>,
+        ),
+        ClientError,
+    > {
+        let first = self.next.fetch_add(1, atomic::Ordering::Relaxed);
+        for i in 0..self.clients.len() {
+            let (http, streams) = &self.clients[(first + i) % self.clients.len()];
+            match streams {
+                None => return Ok((http.clone(), None)),
+                Some(streams) => {
+                    if let Ok(permit) = streams.clone().try_acquire_owned() {
+                        return Ok((http.clone(), Some(permit)));
+                    }
+                }
+            }
+        }
+
+        // All connections are at their limit; wait on the next one.
+        let (http, streams) = &self.clients[first % self.clients.len()];
+        let streams = streams
+            .clone()
+            .expect("clients without a stream limit are never full");
+        return match streams.acquire_owned().await {
+            Ok(permit) => Ok((http.clone(), Some(permit))),
+            Err(err) => Err(ClientError::ConfigError(err.to_string())),
+        };
+    }
+}
+
+pub fn run(
+    config: &Config,
+    plan: &mut Plan,
+    state_tx: &sync::mpsc::Sender<state::Message>,
+) -> Result<(), ClientError> {
+    let workers = sync::Arc::new(atomic::AtomicUsize::new(0));
+    let runtime = match tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .on_thread_start(move || {
+            threadpool::set_worker(workers.fetch_add(1, atomic::Ordering::Relaxed))
+        })
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(err) => return Err(ClientError::ConfigError(err.to_string())),
+    };
+
+    return runtime.block_on(dispatch(config, plan, state_tx));
+}
+
+async fn dispatch(
+    config: &Config,
+    plan: &mut Plan,
+    state_tx: &sync::mpsc::Sender<state::Message>,
+) -> Result<(), ClientError> {
+    let pool_size = config
+        .pool_size
+        .clamp(1, tokio::sync::Semaphore::MAX_PERMITS);
+    let all_permits = match u32::try_from(pool_size) {
+        Ok(all_permits) => all_permits,
+        Err(_) => {
+            return Err(ClientError::ConfigError(format!(
+                "pool size {} is too large",
+                pool_size
+            )))
+        }
+    };
+    let permits = sync::Arc::new(tokio::sync::Semaphore::new(pool_size));
+    let clients = sync::Arc::new(Clients::new(config, pool_size)?);
+
+    // Sleeps are drawn from the workers' streams as requests are dispatched, as for the
+    // threads engine.
+    let mut rngs = random::Workers::new(config.seed, pool_size);
+
+    let start = time::Instant::now();
+    let mut result = Ok(());
+    for request in plan {
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => {
+                result = Err(err);
+                break;
+            }
+        };
+
+        if let Some(delay) = request.delay(start) {
+            tokio::time::sleep(delay).await;
+        }
+
+        let permit = match permits.clone().acquire_owned().await {
+            Ok(permit) => permit,
+            Err(err) => return Err(ClientError::ConfigError(err.to_string())),
+        };
+
+        let clients = clients.clone();
+        let sleep = request.sleep_time().sample(rngs.next());
+        let state_tx = state_tx.clone();
+        let errors = config.errors;
+        let in_flight = config.tracks_in_flight();
+        tokio::spawn(async move {
+            if !sleep.is_zero() {
+                tokio::time::sleep(sleep).await;
+            }
+
+            // A stream is only taken once the request's ready to go, so '--max-streams'
+            // bounds requests in flight rather than ones sleeping.
+            let (http, stream) = match clients.acquire().await {
+                Ok(client) => client,
+                Err(err) => {
+                    let _ = state_tx.send(state::Message::Outcome(Box::new(state::Outcome::new(
+                        &request,
+                        &Err(err),
+                    ))));
+                    return;
+                }
+            };
+
+            if in_flight {
+                let _ = state_tx.send(state::Message::Started);
+            }
+
+            let result = match client::Client::new(&request) {
+                Ok(client) => client.execute_async(&http).await,
+                Err(err) => Err(err),
+            };
+
+            if let Err(err) = &result {
+                if errors {
+                    eprintln!(
+                        "method={} endpoint=\"{}\" error=\"{}\"",
+                        &request.method, &request.endpoint, err,
+                    )
+                }
+            }
+
+            let _ = state_tx.send(state::Message::Outcome(Box::new(state::Outcome::new(
+                &request, &result,
+            ))));
+            drop(stream);
+            drop(permit);
+        });
+    }
+
+    // Wait for in-flight requests to finish.
+    let _ = permits.acquire_many(all_permits).await;
+
+    return result;
+}
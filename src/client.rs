@@ -1,41 +1,118 @@
+#[cfg(feature = "compression")]
+use crate::compression;
 use crate::config;
 use crate::errors::ClientError;
+use crate::timings;
+use std::cell;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path;
+use std::sync::atomic;
+use std::time;
 use ureq;
 
+#[cfg(feature = "async")]
+use http_body_util::BodyExt;
+
 static SPLIT_HEADER_VALUE_CHAR: [char; 2] = [':', '='];
 
+/// Responses seen so far, for sampling '--save-every'.
+static RESPONSES: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
+
+thread_local! {
+    /// Agent (by timeout) reused by this thread's requests, so connections are kept alive.
+    static AGENT: cell::RefCell<Option<(u64, ureq::Agent)>> = const { cell::RefCell::new(None) };
+}
+
+/// What's kept from a response.
+#[derive(Debug, Default, Clone)]
+pub struct Response {
+    pub status: u16,
+    /// Negotiated protocol, e.g. HTTP/1.1 or HTTP/2
+    pub protocol: String,
+    pub timings: timings::Timings,
+    /// Response head and body size; head as HTTP/1.1 text, excluding TLS and framing.
+    pub bytes_received: u64,
+    /// Request head size, as HTTP/1.1 text.
+    pub bytes_sent: u64,
+    /// Content encoding with '--compressed', 'identity' when there wasn't one.
+    pub encoding: String,
+    /// Body size, as received and once decoded.
+    pub body_size: u64,
+    pub decoded_size: u64,
+}
+
+/// A body that's been read.
+struct Body {
+    size: u64,
+    decoded_size: u64,
+    /// Decoded body, only when it's being saved.
+    kept: Vec<u8>,
+}
+
+/// Counts bytes read through it, e.g. the size of a body before it's decoded.
+struct Counter<'a, R> {
+    inner: R,
+    count: &'a mut u64,
+}
+
+impl<R: io::Read> io::Read for Counter<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        *self.count += n as u64;
+        return Ok(n);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Client {
     pub method: String,
     pub endpoint: String,
     headers: Vec<(String, String)>,
+    timeout: u64,
+    save_responses: String,
+    save_every: usize,
+    compressed: bool,
     debug: bool,
 }
 
 impl Client {
-    pub fn new(config: config::Config) -> Result<Client, ClientError> {
-        let mut headers = Vec::<(String, String)>::new();
-
-        for header in config.headers {
-            let header = header.to_header()?;
-            if header.0 != "" {
-                headers.push(header);
-            }
-        }
-
+    pub fn new(config: &config::Config) -> Result<Client, ClientError> {
         return Ok(Client {
-            method: config.method,
-            endpoint: config.endpoint,
-            headers: headers,
+            method: config.method.clone(),
+            endpoint: config.endpoint.clone(),
+            headers: headers(config)?,
+            timeout: config.timeout,
+            save_responses: config.save_responses.clone(),
+            save_every: config.save_every,
+            compressed: config.compressed,
             debug: config.debug,
         });
     }
 
-    // Only returning status code or error right now.
-    pub fn execute(&self) -> Result<u16, ClientError> {
-        let client = ureq::agent();
+    fn agent(&self) -> Result<ureq::Agent, ClientError> {
+        if let Some(agent) = AGENT.with(|a| match &*a.borrow() {
+            Some((timeout, agent)) if *timeout == self.timeout => Some(agent.clone()),
+            _ => None,
+        }) {
+            return Ok(agent);
+        }
+
+        let mut builder = ureq::AgentBuilder::new()
+            .resolver(timings::Resolver)
+            .tls_connector(timings::TlsConnector::shared()?);
+        if self.timeout > 0 {
+            builder = builder.timeout(time::Duration::from_millis(self.timeout));
+        }
+        let agent = builder.build();
+
+        AGENT.with(|a| *a.borrow_mut() = Some((self.timeout, agent.clone())));
+        return Ok(agent);
+    }
 
-        let mut request = client.request(&self.method, &self.endpoint);
+    pub fn execute(&self) -> Result<Response, ClientError> {
+        let mut request = self.agent()?.request(&self.method, &self.endpoint);
 
         for (key, val) in &self.headers {
             request = request.set(key, val);
@@ -45,16 +122,398 @@ impl Client {
             println!("DEBUG:: {:?}", request);
         }
 
+        timings::reset();
+        let start = time::Instant::now();
         match request.call() {
-            Ok(response) => {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => {
+                let headers = time::Instant::now();
                 if self.debug {
                     println!("DEBUG:: {:?}", response);
                 }
-                return Ok(response.status());
+
+                let status = response.status();
+                let protocol = response.http_version().to_string();
+                let mut head = status_line_size(&protocol, response.status_text());
+                for name in response.headers_names() {
+                    for value in response.all(&name) {
+                        head += header_size(&name, value);
+                    }
+                }
+
+                // Read the body so the connection can be reused, keeping it only to save.
+                let encoding = self.encoding(response.header("content-encoding"));
+                let sample = self.sample();
+                let body = self.read_body(response.into_reader(), &encoding, sample.is_some())?;
+                if let Some(n) = sample {
+                    self.save(n, status, &body.kept);
+                }
+
+                return Ok(Response {
+                    status,
+                    protocol,
+                    timings: timings::Timings::new(
+                        &timings::marks(),
+                        start,
+                        headers,
+                        time::Instant::now(),
+                    ),
+                    bytes_received: head + body.size,
+                    bytes_sent: self.request_size(),
+                    encoding,
+                    body_size: body.size,
+                    decoded_size: body.decoded_size,
+                });
+            }
+            Err(ureq::Error::Transport(transport)) => {
+                let kind = match (transport.kind(), transport.source()) {
+                    (ureq::ErrorKind::Io, Some(err)) => match err.downcast_ref::<io::Error>() {
+                        Some(err) => io_kind(err).to_string(),
+                        None => transport.kind().to_string(),
+                    },
+                    (kind, _) => kind.to_string(),
+                };
+                return Err(ClientError::RequestError(kind, transport.to_string()));
+            }
+        }
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn execute_async(&self, http: &HttpClient) -> Result<Response, ClientError> {
+        let mut request = hyper::Request::builder()
+            .method(self.method.as_str())
+            .uri(&self.endpoint);
+
+        for (key, val) in &self.headers {
+            request = request.header(key, val);
+        }
+
+        let request = match request.body(http_body_util::Empty::new()) {
+            Ok(request) => request,
+            Err(err) => {
+                return Err(ClientError::RequestError(
+                    "Bad Request".to_string(),
+                    format!("{}: {}", self.endpoint, err),
+                ))
+            }
+        };
+
+        if self.debug {
+            println!("DEBUG:: {:?}", request);
+        }
+
+        let start = time::Instant::now();
+        let call = async {
+            let response = match http.request(request).await {
+                Ok(response) => response,
+                Err(err) => {
+                    let kind = match err.source().and_then(|e| e.downcast_ref::<hyper::Error>()) {
+                        _ if err.is_connect() => "Connection Failed",
+                        Some(source) => hyper_kind(source),
+                        None => "Network Error",
+                    };
+                    return Err(self.request_error(kind, &err));
+                }
+            };
+            let headers = time::Instant::now();
+            if self.debug {
+                println!("DEBUG:: {:?}", response);
+            }
+
+            let status = response.status().as_u16();
+            let protocol = match response.version() {
+                hyper::Version::HTTP_2 => "HTTP/2".to_string(),
+                version => format!("{:?}", version),
+            };
+            let reason = response.status().canonical_reason().unwrap_or_default();
+            let mut head = status_line_size(&protocol, reason);
+            for (name, value) in response.headers() {
+                head += header_size(name.as_str(), &String::from_utf8_lossy(value.as_bytes()));
+            }
+
+            // Read the body so the connection can be reused, keeping it only to decode or
+            // save.
+            let encoding = self.encoding(
+                response
+                    .headers()
+                    .get("content-encoding")
+                    .and_then(|v| v.to_str().ok()),
+            );
+            let sample = self.sample();
+            let keep = sample.is_some() || self.compressed;
+            let mut frames = response.into_body();
+            let mut kept = vec![];
+            let mut size: u64 = 0;
+            while let Some(frame) = frames.frame().await {
+                let frame = frame.map_err(|err| self.request_error(hyper_kind(&err), &err))?;
+                if let Some(data) = frame.data_ref() {
+                    size += data.len() as u64;
+                    if keep {
+                        kept.extend_from_slice(data);
+                    }
+                }
             }
-            Err(err) => return Err(ClientError::HTTPError(err.to_string())),
+
+            let mut body = Body {
+                size,
+                decoded_size: size,
+                kept,
+            };
+            if keep {
+                body = self.read_body(io::Cursor::new(body.kept), &encoding, sample.is_some())?;
+            }
+            if let Some(n) = sample {
+                self.save(n, status, &body.kept);
+            }
+
+            return Ok::<_, ClientError>((status, protocol, headers, head, encoding, body));
+        };
+
+        let (result, marks) = timings::scope(async {
+            if self.timeout > 0 {
+                let timeout = time::Duration::from_millis(self.timeout);
+                return match tokio::time::timeout(timeout, call).await {
+                    Ok(result) => result,
+                    Err(_) => Err(ClientError::RequestError(
+                        "Timed Out".to_string(),
+                        format!("{}: timed out", self.endpoint),
+                    )),
+                };
+            }
+            return call.await;
+        })
+        .await;
+
+        let (status, protocol, headers, head, encoding, body) = result?;
+        return Ok(Response {
+            status,
+            protocol,
+            timings: timings::Timings::new(&marks, start, headers, time::Instant::now()),
+            bytes_received: head + body.size,
+            bytes_sent: self.request_size(),
+            encoding,
+            body_size: body.size,
+            decoded_size: body.decoded_size,
+        });
+    }
+
+    /// A request error of a kind, with what caused it.
+    #[cfg(feature = "async")]
+    fn request_error(&self, kind: &str, err: &dyn Error) -> ClientError {
+        let mut message = format!("{}: {}", self.endpoint, err);
+        let mut source = err.source();
+        while let Some(err) = source {
+            // Errors often repeat their source's message in their own.
+            let detail = err.to_string();
+            if !message.ends_with(&detail) {
+                message.push_str(&format!(": {}", detail));
+            }
+            source = err.source();
+        }
+        return ClientError::RequestError(kind.to_string(), message);
+    }
+
+    /// Content encoding to report and decode, only with '--compressed'.
+    fn encoding(&self, header: Option<&str>) -> String {
+        if !self.compressed {
+            return String::new();
         }
+        return match header.map(str::trim) {
+            Some(encoding) if !encoding.is_empty() => encoding.to_lowercase(),
+            _ => "identity".to_string(),
+        };
     }
+
+    /// Reads (and with '--compressed' decodes) a body to the end, keeping the decoded body
+    /// when asked.
+    fn read_body(
+        &self,
+        reader: impl io::Read,
+        #[allow(unused)] encoding: &str,
+        keep: bool,
+    ) -> Result<Body, ClientError> {
+        let mut size: u64 = 0;
+        let mut kept = vec![];
+        let decoded_size = {
+            #[allow(unused_mut)]
+            let mut reader: Box<dyn io::Read> = Box::new(Counter {
+                inner: reader,
+                count: &mut size,
+            });
+
+            #[cfg(feature = "compression")]
+            if self.compressed {
+                reader = compression::decoder(encoding, reader)?;
+            }
+
+            let copied = match keep {
+                true => io::copy(&mut reader, &mut kept),
+                false => io::copy(&mut reader, &mut io::sink()),
+            };
+            copied.map_err(|err| {
+                ClientError::RequestError(
+                    io_kind(&err).to_string(),
+                    format!("{}: {}", self.endpoint, err),
+                )
+            })?
+        };
+
+        return Ok(Body {
+            size,
+            decoded_size,
+            kept,
+        });
+    }
+
+    /// Response number to save this response's body as, per '--save-every'.
+    fn sample(&self) -> Option<usize> {
+        if self.save_responses.is_empty() {
+            return None;
+        }
+
+        let n = RESPONSES.fetch_add(1, atomic::Ordering::Relaxed);
+        if !n.is_multiple_of(self.save_every) {
+            return None;
+        }
+        return Some(n);
+    }
+
+    /// Writes a sampled body to '--save-responses', e.g. '000042-200.body'. Failing to
+    /// save doesn't fail the request, it's only reported.
+    fn save(&self, n: usize, status: u16, body: &[u8]) {
+        let file = path::Path::new(&self.save_responses).join(format!("{:06}-{}.body", n, status));
+        if let Err(err) = fs::write(&file, body) {
+            eprintln!("save=\"{}\" error=\"{}\"", file.display(), err);
+        }
+    }
+
+    fn request_size(&self) -> u64 {
+        return request_size(&self.method, &self.endpoint, &self.headers);
+    }
+}
+
+/// Kind of request error for an io error, in the same terms as ureq's; e.g. 'Network Error'.
+fn io_kind(err: &io::Error) -> &'static str {
+    return match err.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => "Timed Out",
+        io::ErrorKind::InvalidData => "Bad Body",
+        _ => "Network Error",
+    };
+}
+
+/// Kind of request error for a hyper error, in the same terms as ureq's.
+#[cfg(feature = "async")]
+fn hyper_kind(err: &hyper::Error) -> &'static str {
+    if err.is_timeout() {
+        return "Timed Out";
+    }
+    if err.is_parse() || err.is_parse_status() {
+        return "Bad Response";
+    }
+    return "Network Error";
+}
+
+/// A request's headers; with 'Accept-Encoding' for '--compressed', unless it's set.
+pub fn headers(config: &config::Config) -> Result<Vec<(String, String)>, ClientError> {
+    let mut headers = Vec::<(String, String)>::new();
+
+    for header in &config.headers {
+        let header = header.clone().to_header()?;
+        if header.0 != "" {
+            headers.push(header);
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    if config.compressed
+        && !headers
+            .iter()
+            .any(|(key, _)| key.eq_ignore_ascii_case("accept-encoding"))
+    {
+        headers.push((
+            "Accept-Encoding".to_string(),
+            compression::ACCEPT_ENCODING.to_string(),
+        ));
+    }
+
+    return Ok(headers);
+}
+
+/// Size of a request head, as HTTP/1.1 text.
+pub fn request_size(method: &str, endpoint: &str, headers: &[(String, String)]) -> u64 {
+    let rest = match endpoint.split_once("://") {
+        Some((_, rest)) => rest,
+        None => endpoint,
+    };
+    let (host, target) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+
+    // "METHOD target HTTP/1.1\r\n" "Host: host\r\n" ... "\r\n"
+    let mut size = (method.len() + target.len() + 12) as u64 + header_size("Host", host) + 2;
+    for (key, val) in headers {
+        size += header_size(key, val);
+    }
+    return size;
+}
+
+/// Size of "HTTP/1.1 200 OK\r\n"
+fn status_line_size(protocol: &str, reason: &str) -> u64 {
+    return (protocol.len() + 5 + reason.len() + 2) as u64;
+}
+
+/// Size of "name: value\r\n"
+fn header_size(name: &str, value: &str) -> u64 {
+    return (name.len() + 2 + value.len() + 2) as u64;
+}
+
+/// Shared, pooled http client used by the async engine; with connectors wrapped to mark
+/// connection phase timings.
+#[cfg(feature = "async")]
+pub type HttpClient = hyper_util::client::legacy::Client<
+    timings::Connector<
+        hyper_tls::HttpsConnector<
+            timings::Connector<
+                hyper_util::client::legacy::connect::HttpConnector<timings::AsyncResolver>,
+            >,
+        >,
+    >,
+    http_body_util::Empty<hyper::body::Bytes>,
+>;
+
+/// Builds a pooled http client for '--http-version'; '2' offers h2 via ALPN falling back to
+/// HTTP/1.1, 'h2c' speaks HTTP/2 with prior knowledge.
+#[cfg(feature = "async")]
+pub fn http_client(config: &config::Config) -> Result<HttpClient, ClientError> {
+    let mut tls = hyper_tls::native_tls::TlsConnector::builder();
+    if config.http_version == "2" {
+        tls.request_alpns(&["h2", "http/1.1"]);
+    }
+    let tls = match tls.build() {
+        Ok(tls) => tls,
+        Err(err) => return Err(ClientError::ConfigError(err.to_string())),
+    };
+
+    let resolver =
+        timings::AsyncResolver(hyper_util::client::legacy::connect::dns::GaiResolver::new());
+    let mut http = hyper_util::client::legacy::connect::HttpConnector::new_with_resolver(resolver);
+    http.enforce_http(false);
+    let http = timings::Connector {
+        inner: http,
+        tls: false,
+    };
+
+    let mut builder =
+        hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new());
+    if config.http_version == "h2c" {
+        builder.http2_only(true);
+    }
+
+    let https = timings::Connector {
+        inner: hyper_tls::HttpsConnector::from((http, tls.into())),
+        tls: true,
+    };
+    return Ok(builder.build(https));
 }
 
 pub trait HeaderStringSplit {
@@ -115,9 +574,10 @@ fn client_new_test() {
         debug: false,
         errors: false,
         output: "default".to_string(),
+        ..config::test_config()
     };
 
-    let cli = Client::new(cfg.clone());
+    let cli = Client::new(&cfg);
     assert!(!cli.is_err());
 
     let cli = cli.unwrap();
@@ -125,7 +585,7 @@ fn client_new_test() {
     assert_eq!(cli.headers[1], ("Bah".to_string(), "boo".to_string()));
 
     cfg.headers = vec!["=ack".to_string()];
-    let cli = Client::new(cfg);
+    let cli = Client::new(&cfg);
     assert!(cli.is_err());
 }
 
@@ -146,3 +606,52 @@ fn to_header_from_string_test() {
     assert!(ugly.is_err());
     assert!(none.is_ok());
 }
+
+#[test]
+fn read_body_test() {
+    let mut c = config::test_config();
+    let client = Client::new(&c).unwrap();
+    let body = client.read_body(&b"hello"[..], "", true).unwrap();
+    assert_eq!((body.size, body.decoded_size), (5, 5));
+    assert_eq!(body.kept, b"hello".to_vec());
+    assert_eq!(client.encoding(Some("gzip")), String::new());
+
+    c.compressed = true;
+    let client = Client::new(&c).unwrap();
+    assert_eq!(client.encoding(None), "identity".to_string());
+    assert_eq!(client.encoding(Some("GZIP")), "gzip".to_string());
+
+    #[cfg(feature = "compression")]
+    {
+        use std::io::Write;
+
+        assert!(client.headers.contains(&(
+            "Accept-Encoding".to_string(),
+            compression::ACCEPT_ENCODING.to_string()
+        )));
+
+        let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gzip.write_all(&[b'a'; 1000]).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let body = client.read_body(&gzip[..], "gzip", false).unwrap();
+        assert_eq!(body.size, gzip.len() as u64);
+        assert_eq!(body.decoded_size, 1000);
+        assert!(body.kept.is_empty());
+    }
+}
+
+#[test]
+fn sizes_test() {
+    assert_eq!(
+        status_line_size("HTTP/1.1", "OK"),
+        "HTTP/1.1 200 OK\r\n".len() as u64
+    );
+    assert_eq!(header_size("Host", "a"), "Host: a\r\n".len() as u64);
+
+    let mut c = config::test_config();
+    c.endpoint = "http://localhost:3000/foo?bar=1".to_string();
+    c.headers = vec!["X-Test:run1".to_string()];
+    let client = Client::new(&c).unwrap();
+    let head = "GET /foo?bar=1 HTTP/1.1\r\nHost: localhost:3000\r\nX-Test: run1\r\n\r\n";
+    assert_eq!(client.request_size(), head.len() as u64);
+}
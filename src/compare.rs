@@ -0,0 +1,486 @@
+//! Comparing a run against a baseline, for 'compare'.
+//!
+//! Both are '--output json' summaries. Throughput, error rate and total latency percentiles
+//! are tested for a significant change (two sided, at 95%); a regression is a significant
+//! change for the worse that's past its tolerance. Script rows are matched by row number
+//! and only get deltas, as there's too little kept on them to test.
+
+use crate::config::CompareConfig;
+use crate::errors::ClientError;
+use crate::report::RowSerialize;
+use crate::state::{PhaseSerialize, StateSerialize};
+
+use std::collections;
+use std::fmt::Write;
+use std::fs;
+
+use serde_derive::Deserialize;
+
+/// |z| past this is a significant change.
+static Z_CRITICAL: f64 = 1.96;
+
+static PERCENTILES: [&str; 3] = ["p50", "p90", "p99"];
+
+/// A run's json output; latencies and rows are missing from older versions'.
+#[derive(Deserialize)]
+struct Run {
+    /// Read on its own; flattening can't take 'took' being a u128.
+    #[serde(skip)]
+    state: StateSerialize,
+    #[serde(default)]
+    timings: collections::BTreeMap<String, PhaseSerialize>,
+    #[serde(default)]
+    latencies: Vec<(f64, u64)>,
+    #[serde(default)]
+    rows: Vec<RowSerialize>,
+}
+
+impl Run {
+    fn load(file: &str) -> Result<Run, ClientError> {
+        let json = match fs::read_to_string(file) {
+            Ok(json) => json,
+            Err(err) => {
+                return Err(ClientError::ConfigError(format!(
+                    "invalid run file '{}': {}",
+                    file, err
+                )))
+            }
+        };
+        return Run::from_str(&json)
+            .map_err(|err| ClientError::ConfigError(format!("{}: {}", file, err)));
+    }
+
+    fn from_str(json: &str) -> Result<Run, serde_json::Error> {
+        let mut run: Run = serde_json::from_str(json)?;
+        run.state = serde_json::from_str(json)?;
+        return Ok(run);
+    }
+
+    fn rps(&self) -> f64 {
+        if self.state.took == 0 {
+            return 0.0;
+        }
+        return self.state.processed as f64 / (self.state.took as f64 / 1000.0);
+    }
+
+    fn errors(&self) -> usize {
+        return self.state.fail + self.state.error;
+    }
+
+    /// Total latency percentile, e.g. 'p99', in milliseconds.
+    fn percentile(&self, p: &str) -> f64 {
+        let total = match self.timings.get("total") {
+            Some(total) => total,
+            None => return 0.0,
+        };
+        return match p {
+            "p50" => total.p50,
+            "p90" => total.p90,
+            _ => total.p99,
+        };
+    }
+
+    /// How many latencies are at or under 'ms', out of how many.
+    fn at_or_under(&self, ms: f64) -> (u64, u64) {
+        let under = self
+            .latencies
+            .iter()
+            .filter(|(value, _)| *value <= ms)
+            .map(|(_, count)| count)
+            .sum();
+        let total = self.latencies.iter().map(|(_, count)| count).sum();
+        return (under, total);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Delta {
+    metric: String,
+    unit: &'static str,
+    baseline: f64,
+    current: f64,
+    /// Percent change; percentage points for rates.
+    change: f64,
+    /// Unknown when there's nothing to test with.
+    significant: Option<bool>,
+    regression: bool,
+}
+
+impl Delta {
+    fn change_string(&self) -> String {
+        return match self.unit {
+            "%" => format!("{:+.2}pp", self.change),
+            _ => format!("{:+.2}%", self.change),
+        };
+    }
+
+    fn significant_string(&self) -> &'static str {
+        return match self.significant {
+            Some(true) => "significant",
+            Some(false) => "",
+            None => "untested",
+        };
+    }
+}
+
+/// Compares the runs, printing deltas; returns how many regressions there were.
+pub fn compare(config: &CompareConfig) -> Result<usize, ClientError> {
+    let baseline = Run::load(&config.baseline)?;
+    let current = Run::load(&config.current)?;
+
+    let deltas = deltas(&baseline, &current, config);
+    let regressions = deltas.iter().filter(|delta| delta.regression).count();
+
+    println!("{}", table(&deltas));
+    let rows = row_deltas(&baseline, &current);
+    if !rows.is_empty() {
+        println!("\n{}", rows_table(&rows, config));
+    }
+    println!("\nregressions={}", regressions);
+    return Ok(regressions);
+}
+
+fn deltas(baseline: &Run, current: &Run, config: &CompareConfig) -> Vec<Delta> {
+    let mut deltas = vec![];
+
+    let (b, c) = (baseline.rps(), current.rps());
+    let significant = poisson_z(
+        baseline.state.processed as f64,
+        baseline.state.took as f64 / 1000.0,
+        current.state.processed as f64,
+        current.state.took as f64 / 1000.0,
+    )
+    .map(|z| z.abs() > Z_CRITICAL);
+    let change = percent_change(b, c);
+    deltas.push(Delta {
+        metric: "rps".to_string(),
+        unit: "",
+        baseline: b,
+        current: c,
+        change,
+        significant,
+        regression: -change > config.throughput_tolerance && significant != Some(false),
+    });
+
+    let (b, c) = (
+        rate(baseline.errors(), baseline.state.processed),
+        rate(current.errors(), current.state.processed),
+    );
+    let significant = two_proportion_z(
+        baseline.errors() as u64,
+        baseline.state.processed as u64,
+        current.errors() as u64,
+        current.state.processed as u64,
+    )
+    .map(|z| z.abs() > Z_CRITICAL);
+    deltas.push(Delta {
+        metric: "error_rate".to_string(),
+        unit: "%",
+        baseline: b,
+        current: c,
+        change: c - b,
+        significant,
+        regression: c - b > config.error_rate_tolerance && significant != Some(false),
+    });
+
+    // Whether as many of the current run's latencies are under the baseline's percentile.
+    for p in PERCENTILES {
+        let (b, c) = (baseline.percentile(p), current.percentile(p));
+        let (b_under, b_total) = baseline.at_or_under(b);
+        let (c_under, c_total) = current.at_or_under(b);
+        let significant =
+            two_proportion_z(b_under, b_total, c_under, c_total).map(|z| z.abs() > Z_CRITICAL);
+        let change = percent_change(b, c);
+        deltas.push(Delta {
+            metric: p.to_string(),
+            unit: "ms",
+            baseline: b,
+            current: c,
+            change,
+            significant,
+            regression: change > config.latency_tolerance && significant != Some(false),
+        });
+    }
+    return deltas;
+}
+
+/// Rows in both runs, by row number: p50, p99 and error rate deltas.
+fn row_deltas(baseline: &Run, current: &Run) -> Vec<(usize, String, Vec<Delta>)> {
+    let mut rows = vec![];
+    for base in &baseline.rows {
+        let cur = match current.rows.iter().find(|cur| cur.row == base.row) {
+            Some(cur) => cur,
+            None => continue,
+        };
+
+        let mut deltas = vec![];
+        for (metric, b, c) in [("p50", base.p50, cur.p50), ("p99", base.p99, cur.p99)] {
+            deltas.push(Delta {
+                metric: metric.to_string(),
+                unit: "ms",
+                baseline: b,
+                current: c,
+                change: percent_change(b, c),
+                significant: None,
+                regression: false,
+            });
+        }
+        let (b, c) = (row_error_rate(base), row_error_rate(cur));
+        deltas.push(Delta {
+            metric: "error_rate".to_string(),
+            unit: "%",
+            baseline: b,
+            current: c,
+            change: c - b,
+            significant: None,
+            regression: false,
+        });
+        rows.push((cur.row, cur.request.clone(), deltas));
+    }
+    return rows;
+}
+
+fn table(deltas: &[Delta]) -> String {
+    let mut out = format!(
+        "{:<12}{:>14}{:>14}{:>12}  {}",
+        "metric", "baseline", "current", "change", "result"
+    );
+    for delta in deltas {
+        let result = match delta.regression {
+            true => format!("{} regression", delta.significant_string()),
+            false => delta.significant_string().to_string(),
+        };
+        let line = format!(
+            "{:<12}{:>14}{:>14}{:>12}  {}",
+            delta.metric,
+            format!("{:.3}{}", delta.baseline, delta.unit),
+            format!("{:.3}{}", delta.current, delta.unit),
+            delta.change_string(),
+            result.trim()
+        );
+        let _ = write!(out, "\n{}", line.trim_end());
+    }
+    return out;
+}
+
+/// Row deltas, marking those past tolerance.
+fn rows_table(rows: &[(usize, String, Vec<Delta>)], config: &CompareConfig) -> String {
+    let mut out = format!(
+        "{:<6}{:<12}{:>14}{:>14}{:>12}  {}",
+        "row", "metric", "baseline", "current", "change", "request"
+    );
+    for (row, request, deltas) in rows {
+        for delta in deltas {
+            let past = match delta.unit {
+                "%" => delta.change > config.error_rate_tolerance,
+                _ => delta.change > config.latency_tolerance,
+            };
+            let _ = write!(
+                out,
+                "\n{:<6}{:<12}{:>14}{:>14}{:>12}  {}{}",
+                row,
+                delta.metric,
+                format!("{:.3}{}", delta.baseline, delta.unit),
+                format!("{:.3}{}", delta.current, delta.unit),
+                delta.change_string(),
+                request,
+                if past { " (past tolerance)" } else { "" }
+            );
+        }
+    }
+    return out;
+}
+
+fn percent_change(baseline: f64, current: f64) -> f64 {
+    if baseline == 0.0 {
+        return match current == 0.0 {
+            true => 0.0,
+            false => f64::INFINITY,
+        };
+    }
+    return (current - baseline) / baseline * 100.0;
+}
+
+/// As a percentage.
+fn rate(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    return count as f64 / total as f64 * 100.0;
+}
+
+fn row_error_rate(row: &RowSerialize) -> f64 {
+    return rate(row.fail + row.error, row.success + row.fail + row.error);
+}
+
+/// z for the difference in two proportions, pooled; none when either is empty.
+fn two_proportion_z(x1: u64, n1: u64, x2: u64, n2: u64) -> Option<f64> {
+    if n1 == 0 || n2 == 0 {
+        return None;
+    }
+    let (n1, n2) = (n1 as f64, n2 as f64);
+    let (p1, p2) = (x1 as f64 / n1, x2 as f64 / n2);
+    let pooled = (x1 + x2) as f64 / (n1 + n2);
+    let se = (pooled * (1.0 - pooled) * (1.0 / n1 + 1.0 / n2)).sqrt();
+    if se == 0.0 {
+        return Some(0.0);
+    }
+    return Some((p2 - p1) / se);
+}
+
+/// z for the difference in two Poisson rates; counts over seconds.
+fn poisson_z(count1: f64, secs1: f64, count2: f64, secs2: f64) -> Option<f64> {
+    if secs1 == 0.0 || secs2 == 0.0 {
+        return None;
+    }
+    let se = (count1 / (secs1 * secs1) + count2 / (secs2 * secs2)).sqrt();
+    if se == 0.0 {
+        return Some(0.0);
+    }
+    return Some((count2 / secs2 - count1 / secs1) / se);
+}
+
+#[cfg(test)]
+fn test_run(took: u128, processed: usize, error: usize, latencies: Vec<(f64, u64)>) -> Run {
+    let json = serde_json::json!({
+        "took": took,
+        "requested": processed,
+        "processed": processed,
+        "success": processed - error,
+        "fail": 0,
+        "error": error,
+        "bytes_received": 0,
+        "bytes_sent": 0,
+        "throughput": 0.0,
+        "bytes_compressed": 0,
+        "bytes_decompressed": 0,
+        "protocols": "",
+        "encodings": "",
+        "timings": {},
+    });
+    let mut run = Run::from_str(&json.to_string()).unwrap();
+    run.latencies = latencies;
+    return run;
+}
+
+#[cfg(test)]
+fn test_compare_config() -> CompareConfig {
+    return CompareConfig {
+        baseline: "".to_string(),
+        current: "".to_string(),
+        latency_tolerance: 10.0,
+        throughput_tolerance: 10.0,
+        error_rate_tolerance: 1.0,
+    };
+}
+
+#[test]
+fn z_test() {
+    assert_eq!(two_proportion_z(0, 0, 1, 10), None);
+    assert_eq!(two_proportion_z(0, 100, 0, 100), Some(0.0));
+    assert!(two_proportion_z(10, 1000, 12, 1000).unwrap().abs() < Z_CRITICAL);
+    assert!(two_proportion_z(10, 1000, 50, 1000).unwrap() > Z_CRITICAL);
+
+    assert_eq!(poisson_z(100.0, 0.0, 100.0, 1.0), None);
+    assert!(poisson_z(10_000.0, 10.0, 9_900.0, 10.0).unwrap().abs() < Z_CRITICAL);
+    assert!(poisson_z(10_000.0, 10.0, 8_000.0, 10.0).unwrap() < -Z_CRITICAL);
+}
+
+#[test]
+fn deltas_test() {
+    let config = test_compare_config();
+    let mut baseline = test_run(10_000, 10_000, 10, vec![(1.0, 5_000), (2.0, 5_000)]);
+    baseline.timings.insert(
+        "total".to_string(),
+        PhaseSerialize {
+            count: 10_000,
+            min: 1.0,
+            mean: 1.5,
+            p50: 1.0,
+            p90: 2.0,
+            p95: 2.0,
+            p99: 2.0,
+            max: 2.0,
+        },
+    );
+
+    // The same again; nothing's changed.
+    let mut current = test_run(10_000, 10_000, 10, baseline.latencies.clone());
+    current
+        .timings
+        .insert("total".to_string(), baseline.timings["total"].clone());
+    let found = deltas(&baseline, &current, &config);
+    assert_eq!(found.len(), 5);
+    assert!(found
+        .iter()
+        .all(|d| d.significant == Some(false) && !d.regression));
+
+    // Slower, less of it and more errors.
+    let mut current = test_run(12_500, 10_000, 200, vec![(1.0, 1_000), (3.0, 9_000)]);
+    current.timings.insert(
+        "total".to_string(),
+        PhaseSerialize {
+            p50: 3.0,
+            p90: 3.0,
+            p99: 3.0,
+            ..baseline.timings["total"].clone()
+        },
+    );
+    let found = deltas(&baseline, &current, &config);
+    let metrics: Vec<&str> = found.iter().map(|d| d.metric.as_str()).collect();
+    assert_eq!(metrics, vec!["rps", "error_rate", "p50", "p90", "p99"]);
+    assert_eq!(found[0].change, -20.0);
+    assert!((found[1].change - 1.9).abs() < 1e-9);
+    assert_eq!(found[2].change, 200.0);
+    assert!(found
+        .iter()
+        .all(|d| d.significant == Some(true) && d.regression));
+
+    // Within tolerance isn't a regression, significant or not.
+    let config = CompareConfig {
+        latency_tolerance: 250.0,
+        throughput_tolerance: 25.0,
+        error_rate_tolerance: 2.0,
+        ..config
+    };
+    assert!(deltas(&baseline, &current, &config)
+        .iter()
+        .all(|d| !d.regression));
+
+    // Nothing to test latencies with from an older run's output; past tolerance is enough.
+    current.latencies = vec![];
+    let found = deltas(&baseline, &current, &test_compare_config());
+    assert_eq!(found[4].significant, None);
+    assert!(found[4].regression);
+}
+
+#[test]
+fn compare_test() {
+    let dir = std::env::temp_dir();
+    let baseline = dir.join(format!("noop-client-baseline-{}.json", std::process::id()));
+    let current = dir.join(format!("noop-client-current-{}.json", std::process::id()));
+
+    let json = r#"{"took":1000,"requested":100,"processed":100,"success":100,"fail":0,"error":0,"bytes_received":0,"bytes_sent":0,"throughput":0.0,"bytes_compressed":0,"bytes_decompressed":0,"protocols":"HTTP/1.1:100","encodings":"","timings":{"total":{"count":100,"min":1.0,"mean":1.0,"p50":1.0,"p90":1.0,"p95":1.0,"p99":1.0,"max":1.0}},"latencies":[[1.0,100]],"rows":[{"row":1,"request":"GET http://localhost:3000/","success":100,"fail":0,"error":0,"codes":"200:100","errors":"","mean":1.0,"p50":1.0,"p90":1.0,"p99":1.0,"max":1.0}]}"#;
+    fs::write(&baseline, json).unwrap();
+    fs::write(
+        &current,
+        json.replace(r#""error":0,"bytes"#, r#""error":50,"bytes"#),
+    )
+    .unwrap();
+
+    let config = CompareConfig {
+        baseline: baseline.to_str().unwrap().to_string(),
+        current: current.to_str().unwrap().to_string(),
+        ..test_compare_config()
+    };
+    assert_eq!(compare(&config).unwrap(), 1);
+
+    let config = CompareConfig {
+        current: "missing.json".to_string(),
+        ..config
+    };
+    assert!(compare(&config).is_err());
+
+    let _ = fs::remove_file(baseline);
+    let _ = fs::remove_file(current);
+}
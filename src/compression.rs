@@ -0,0 +1,65 @@
+//! Response body decoding for '--compressed'.
+
+use crate::errors::ClientError;
+
+use std::io;
+
+pub static ACCEPT_ENCODING: &str = "gzip, deflate, br, zstd";
+
+/// Wraps 'reader' to decode a 'Content-Encoding' header value; encodings listed as applied,
+/// so they're decoded in reverse.
+pub fn decoder<'a>(
+    encoding: &str,
+    reader: Box<dyn io::Read + 'a>,
+) -> Result<Box<dyn io::Read + 'a>, ClientError> {
+    let mut reader = reader;
+    for coding in encoding.rsplit(',').map(|c| c.trim().to_lowercase()) {
+        reader = match coding.as_str() {
+            "" | "identity" => reader,
+            "gzip" | "x-gzip" => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+            "deflate" => Box::new(flate2::read::ZlibDecoder::new(reader)),
+            "br" => Box::new(brotli_decompressor::Decompressor::new(reader, 4096)),
+            "zstd" => match zstd::stream::read::Decoder::new(reader) {
+                Ok(decoder) => Box::new(decoder),
+                Err(err) => return Err(ClientError::HTTPError(err.to_string())),
+            },
+            _ => {
+                return Err(ClientError::HTTPError(format!(
+                    "unsupported content-encoding: {}",
+                    coding
+                )))
+            }
+        };
+    }
+    return Ok(reader);
+}
+
+#[test]
+fn decoder_test() {
+    use std::io::{Read, Write};
+
+    let body = b"hello hello hello hello".to_vec();
+    let decode = |encoding: &str, encoded: Vec<u8>| {
+        let mut decoded = vec![];
+        decoder(encoding, Box::new(io::Cursor::new(encoded)))
+            .unwrap()
+            .read_to_end(&mut decoded)
+            .unwrap();
+        decoded
+    };
+
+    let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    gzip.write_all(&body).unwrap();
+    let gzip = gzip.finish().unwrap();
+    assert_eq!(decode("gzip", gzip.clone()), body);
+
+    let mut deflate = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+    deflate.write_all(&gzip).unwrap();
+    assert_eq!(decode("gzip, deflate", deflate.finish().unwrap()), body);
+
+    let zstd = zstd::stream::encode_all(&body[..], 0).unwrap();
+    assert_eq!(decode("zstd", zstd), body);
+
+    assert_eq!(decode("identity", body.clone()), body);
+    assert!(decoder("lzma", Box::new(io::empty())).is_err());
+}
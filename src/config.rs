@@ -1,19 +1,39 @@
+use crate::client::HeaderStringSplit;
 use crate::errors::ClientError;
+use crate::output;
+use crate::random;
+use crate::replay;
 use std::fs;
+use std::{collections, ffi, io, iter, path};
 use std::{thread, time};
 
-#[cfg(any(feature = "json", feature = "yaml"))]
-use std::ffi;
+use std::fmt;
 
-#[cfg(any(feature = "json", feature = "yaml"))]
-use std::path;
+#[cfg(feature = "json")]
+use std::io::BufRead;
 
-use clap::Parser;
-use serde_derive::Deserialize;
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+use std::marker;
+
+use clap::{Args, Parser, Subcommand};
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+use serde::de;
+use serde_derive::{Deserialize, Serialize};
 
 /// This is a (hopefully) simple method of sending http requests (kind of like curl). Either directly; or via a pipe delimited text file
-#[derive(Parser, Debug, Clone)]
-#[command(version, about, long_about = None)]
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(flatten)]
+    config: Config,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Options for a run; also what each script row is made from. Serialized to send a plan to
+/// agents.
+#[derive(Args, Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     /// File path containing a list of options to be used, in place of other arguments
     #[arg(long = "script", short = 'f', default_value = "")]
@@ -31,6 +51,10 @@ pub struct Config {
     #[arg(long, short = 'x', default_value = "")]
     pub headers: Vec<String>,
 
+    /// Request timeout (in milliseconds); 0 disables the timeout
+    #[arg(long = "timeout", short = 't', default_value = "0")]
+    pub timeout: u64,
+
     /// Number of requests to make for each endpoint
     #[arg(long, short = 'n', default_value_t = 1)]
     pub iterations: usize,
@@ -39,14 +63,154 @@ pub struct Config {
     #[arg(long = "sleep", short = 's', default_value = "0")]
     pub sleep: u64,
 
+    /// Random jitter (in milliseconds) to add to or take off each sleep, up to this much either way
+    #[arg(long = "sleep-jitter", default_value = "0")]
+    pub sleep_jitter: u64,
+
+    /// Virtual users' think time before each request, e.g. 200ms, uniform:1s,3s, normal:2s,500ms, exp:2s or pareto:1s,1.5
+    #[arg(long = "think-time", default_value = "0", value_parser = random::Distribution::parse)]
+    pub think_time: random::Distribution,
+
     /// Number of parallel requests
     #[arg(long = "pool-size", short = 'p', default_value = "100")]
     pub pool_size: usize,
 
-    /// Output format; options: default, json, csv, (with features) yaml, json
+    /// Virtual users, each looping through the script on a thread of its own, with think times; 0 disables
+    #[arg(long = "users", short = 'u', default_value = "0")]
+    pub users: usize,
+
+    /// Times each virtual user loops through the script
+    #[arg(long = "loops", default_value = "1")]
+    pub loops: usize,
+
+    /// Draw requests from script rows at random, by their weight, in place of running each row's iterations in order
+    #[arg(long = "mix", default_value = "false", default_missing_value = "true")]
+    pub mix: bool,
+
+    /// Requests to draw with '--mix'; 0 draws as many as the rows' iterations add up to, or runs for '--duration'
+    #[arg(long = "requests", default_value = "0")]
+    pub requests: usize,
+
+    /// How long to draw requests for with '--mix' (e.g. 30s, 5m); 0 runs to '--requests'
+    #[arg(long = "duration", default_value = "0", value_parser = parse_duration)]
+    pub duration: time::Duration,
+
+    /// Run script rows in a random order; rows are loaded up front
+    #[arg(
+        long = "shuffle",
+        default_value = "false",
+        default_missing_value = "true"
+    )]
+    pub shuffle: bool,
+
+    /// Seed for random draws, to make a run again as it was; 0 picks one
+    #[arg(long = "seed", default_value = "0")]
+    pub seed: u64,
+
+    /// Script row's share of requests with '--mix', relative to other rows'
+    #[arg(skip = 1.0)]
+    pub weight: f64,
+
+    /// HTTP version; options: 1.1, (with features) 2, h2c. 2 negotiates HTTP/2 over TLS, h2c uses HTTP/2 with prior knowledge
+    #[arg(long = "http-version", default_value = "1.1")]
+    pub http_version: String,
+
+    /// Shorthand for '--http-version=2'
+    #[arg(
+        long = "http2",
+        default_value = "false",
+        default_missing_value = "true"
+    )]
+    pub http2: bool,
+
+    /// Max concurrent HTTP/2 streams per connection; 0 multiplexes all requests to a host over one connection
+    #[arg(long = "max-streams", default_value = "0")]
+    pub max_streams: usize,
+
+    /// Request engine; options: threads, (with features) async
+    #[arg(long = "engine", default_value = "threads")]
+    pub engine: String,
+
+    /// Output format; options: default, csv, markdown, junit, (with features) json, yaml
     #[arg(long = "output", short = 'o', default_value = "default")]
     pub output: String,
 
+    /// Access log (common, combined or JSON lines) to replay against '--endpoint'
+    #[arg(long = "replay", default_value = "")]
+    pub replay: String,
+
+    /// Speed multiplier for replayed inter-arrival times; 0 replays at max rate
+    #[arg(long = "replay-speed", default_value = "1.0")]
+    pub replay_speed: f64,
+
+    /// Send 'Accept-Encoding' and decode gzip, deflate, br and zstd responses (with features)
+    #[arg(
+        long = "compressed",
+        default_value = "false",
+        default_missing_value = "true"
+    )]
+    pub compressed: bool,
+
+    /// Directory to write sampled response bodies to, for debugging
+    #[arg(long = "save-responses", default_value = "")]
+    pub save_responses: String,
+
+    /// Save every Nth response body with '--save-responses'
+    #[arg(long = "save-every", default_value = "1")]
+    pub save_every: usize,
+
+    /// Report on each interval (e.g. 500ms, 5s, 1m) while running, in the output format; 0 disables
+    #[arg(long = "report-interval", default_value = "0", value_parser = parse_duration)]
+    pub report_interval: time::Duration,
+
+    /// Replay offset (in milliseconds) from the start of the run
+    #[arg(skip)]
+    pub offset: u64,
+
+    /// Script row (from 1) this request came from
+    #[arg(skip)]
+    pub row: usize,
+
+    /// Where in a script this request came from, e.g. 'script.txt:3'
+    #[arg(skip)]
+    pub source: String,
+
+    /// Show a live dashboard in place of other output while running (with features)
+    #[arg(long = "tui", default_value = "false", default_missing_value = "true")]
+    pub tui: bool,
+
+    /// Address to serve Prometheus metrics on at '/metrics' while running, e.g. 127.0.0.1:9090 (with features)
+    #[arg(long = "metrics-addr", default_value = "")]
+    pub metrics_addr: String,
+
+    /// Prometheus push gateway URL to push metrics to once done, e.g. http://localhost:9091/metrics/job/noop-client (with features)
+    #[arg(long = "metrics-push", default_value = "")]
+    pub metrics_push: String,
+
+    /// StatsD address to send per request metrics to over UDP, e.g. 127.0.0.1:8125
+    #[arg(long = "statsd", default_value = "")]
+    pub statsd: String,
+
+    /// Prefix for StatsD metric names
+    #[arg(long = "statsd-prefix", default_value = "noop_client")]
+    pub statsd_prefix: String,
+
+    /// InfluxDB line protocol write URL (e.g. http://localhost:8086/write?db=noop), or a file to append to, for per request points
+    #[arg(long = "influx", default_value = "")]
+    pub influx: String,
+
+    /// File to write a record per request to, for offline analysis; csv or (with features) json lines, by extension
+    #[arg(long = "results-file", default_value = "")]
+    pub results_file: String,
+
+    /// Summarize a '--results-file' from a past run in the output format, in place of making requests
+    #[arg(long = "summarize", default_value = "")]
+    pub summarize: String,
+
+    /// HTML file to write a report with charts to once done, e.g. report.html; also with '--summarize'
+    #[arg(long = "report", default_value = "")]
+    pub report: String,
+
     /// Enable verbose output
     #[arg(
         long = "verbose",
@@ -73,6 +237,129 @@ pub struct Config {
         default_missing_value = "true"
     )]
     pub errors: bool,
+
+    /// Check every script row for problems, reporting each, before sending anything
+    #[arg(
+        long = "check",
+        default_value = "false",
+        default_missing_value = "true"
+    )]
+    pub check: bool,
+
+    /// Print the plan, per script row and how long it'd take at least, in place of sending anything; as json with '--output=json'
+    #[arg(
+        long = "dry-run",
+        default_value = "false",
+        default_missing_value = "true"
+    )]
+    pub dry_run: bool,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Make requests; the default, without a command
+    Run(Box<Config>),
+
+    /// Parse and lint a script, or request, without sending any traffic
+    Validate(Box<Config>),
+
+    /// Translate a script between pipe delimited, json and yaml (with features)
+    Convert(ConvertConfig),
+
+    /// Re-render a saved '--results-file' in an output format
+    Report(ReportConfig),
+
+    /// Compare a run's json output against a baseline's; exits non-zero on regressions
+    Compare(CompareConfig),
+
+    /// Make requests for coordinators, streaming back results (with features)
+    Agent(AgentConfig),
+
+    /// Make requests, split across agents, merging their results (with features)
+    Coordinator(CoordinatorConfig),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ConvertConfig {
+    /// Script file to write, in the format of its extension (txt, csv, json, jsonl or yaml)
+    pub to: String,
+
+    #[command(flatten)]
+    pub config: Box<Config>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ReportConfig {
+    /// Results file from a past run's '--results-file'
+    pub results: String,
+
+    /// Output format
+    #[arg(long = "output", short = 'o', default_value = "default")]
+    pub output: String,
+
+    /// HTML file to write a report with charts to, e.g. report.html
+    #[arg(long = "report", default_value = "")]
+    pub report: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CompareConfig {
+    /// Baseline run's json output
+    pub baseline: String,
+
+    /// Run's json output, to compare against the baseline
+    pub current: String,
+
+    /// Tolerated increase in latency percentiles (in percent)
+    #[arg(long = "latency-tolerance", default_value = "10")]
+    pub latency_tolerance: f64,
+
+    /// Tolerated drop in throughput, as requests per second (in percent)
+    #[arg(long = "throughput-tolerance", default_value = "10")]
+    pub throughput_tolerance: f64,
+
+    /// Tolerated increase in error rate (in percentage points)
+    #[arg(long = "error-rate-tolerance", default_value = "1")]
+    pub error_rate_tolerance: f64,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct AgentConfig {
+    /// Address to listen for coordinators on
+    #[arg(long = "listen", short = 'l', default_value = "127.0.0.1:4000")]
+    pub listen: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CoordinatorConfig {
+    /// Agent addresses to split the run across, e.g. 10.0.0.2:4000,10.0.0.3:4000
+    #[arg(long = "agents", short = 'a', required = true, value_delimiter = ',')]
+    pub agents: Vec<String>,
+
+    #[command(flatten)]
+    pub config: Box<Config>,
+}
+
+/// Parses durations like '250ms', '5s', '1.5m' or '1h'; bare numbers are seconds.
+pub fn parse_duration(value: &str) -> Result<time::Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: f64 = match number.parse() {
+        Ok(number) => number,
+        Err(_) => return Err(format!("invalid duration: {}", value)),
+    };
+    let secs = match unit.trim() {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("invalid duration unit: {}", unit)),
+    };
+    return Ok(time::Duration::from_secs_f64(secs));
 }
 
 fn default_string() -> String {
@@ -87,7 +374,11 @@ fn default_u64() -> u64 {
     return 0;
 }
 
-#[derive(Debug, Deserialize, Default)]
+fn default_f64() -> f64 {
+    return 0.0;
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 struct ConfigDeserializer {
     #[serde(default = "default_usize")]
     pub iterations: usize,
@@ -101,207 +392,1549 @@ struct ConfigDeserializer {
     #[serde(default = "default_string")]
     pub headers: String,
 
+    #[serde(default = "default_u64")]
+    pub timeout: u64,
+
+    #[serde(default = "default_u64")]
+    pub sleep: u64,
+
+    /// Think time for virtual users, e.g. 'exp:200ms'; see 'random::Distribution::parse'.
+    #[serde(default = "default_string")]
+    pub think_time: String,
+
+    /// Share of requests with '--mix'; 0 leaves the default of 1.
+    #[serde(default = "default_f64")]
+    pub weight: f64,
+
+    /// Line the row's on, in line based scripts.
+    #[serde(skip)]
+    pub line: usize,
+}
+
+/// Columns, or keys, a script row can have.
+static ROW_KEYS: [&str; 8] = [
+    "iterations",
+    "method",
+    "endpoint",
+    "headers",
+    "timeout",
+    "sleep",
+    "think_time",
+    "weight",
+];
+
+/// Keys a script document can have, besides its rows.
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+static DOCUMENT_KEYS: [&str; 2] = ["defaults", "vars"];
+
+/// Methods a row's linted against.
+static METHODS: [&str; 9] = [
+    "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH",
+];
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Script rows, yielded lazily; see 'Config::rows'.
+pub type Rows = Box<dyn Iterator<Item = Result<Config, ClientError>> + Send>;
+
+/// Script level defaults, applied to each row before the row's own values.
+#[derive(Debug, Deserialize, Default, Clone)]
+struct Defaults {
+    #[serde(default = "default_string")]
+    pub base_url: String,
+
+    #[serde(default = "default_string")]
+    pub headers: String,
+
+    #[serde(default = "default_u64")]
+    pub timeout: u64,
+
     #[serde(default = "default_u64")]
     pub sleep: u64,
 }
 
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+enum Var {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Var::String(v) => return write!(f, "{}", v),
+            Var::Integer(v) => return write!(f, "{}", v),
+            Var::Float(v) => return write!(f, "{}", v),
+            Var::Bool(v) => return write!(f, "{}", v),
+        };
+    }
+}
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+/// A script with a top-level document, e.g.
+///
+/// defaults: { base_url: http://localhost:3000, headers: "User-Agent:noop-client" }
+/// vars: { run: run1 }
+/// include: [ other_script.yaml ]
+/// requests: [ { iterations: 6, endpoint: /request1, headers: "X-Test:${run}" } ]
+///
+/// TOML scripts use '[defaults]', '[vars]' and '[[request]]' tables.
+///
+/// Rows are read as the format's own value, 'R', then each deserialized on its own; so one
+/// bad row doesn't hide the rest.
+#[derive(Debug, Deserialize)]
+struct ScriptDocument<R> {
+    #[serde(default)]
+    pub defaults: Defaults,
+
+    #[serde(default)]
+    pub vars: collections::HashMap<String, Var>,
+
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    #[serde(default = "Vec::new", alias = "request")]
+    pub requests: Vec<R>,
+}
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+/// Scripts are either a bare list of rows, or a document.
+#[derive(Debug)]
+enum Script<R> {
+    Rows(Vec<R>),
+    Document(ScriptDocument<R>),
+}
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+/// A script row, or why it doesn't deserialize.
+type Record = Result<ConfigDeserializer, ClientError>;
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+/// By the shape of the top level, rather than untagged; so errors within keep their line
+/// and column.
+impl<'de, R: de::Deserialize<'de>> de::Deserialize<'de> for Script<R> {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ScriptVisitor<R>(marker::PhantomData<R>);
+
+        impl<'de, R: de::Deserialize<'de>> de::Visitor<'de> for ScriptVisitor<R> {
+            type Value = Script<R>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                return write!(f, "a list of rows, or a document");
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Script<R>, A::Error> {
+                let rows =
+                    de::Deserialize::deserialize(de::value::SeqAccessDeserializer::new(seq))?;
+                return Ok(Script::Rows(rows));
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Script<R>, A::Error> {
+                let document =
+                    de::Deserialize::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                return Ok(Script::Document(document));
+            }
+        }
+
+        return deserializer.deserialize_any(ScriptVisitor(marker::PhantomData));
+    }
+}
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+/// Deserializes request 'n' of a script, in place; so its error has its location.
+#[derive(Clone, Copy)]
+struct RowAt(usize);
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+impl<'de> de::DeserializeSeed<'de> for RowAt {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        return deserializer.deserialize_any(self);
+    }
+}
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+impl<'de> de::Visitor<'de> for RowAt {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "a list of rows, or a document");
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        for _ in 0..self.0 {
+            seq.next_element::<de::IgnoredAny>()?;
+        }
+        seq.next_element::<ConfigDeserializer>()?;
+        while seq.next_element::<de::IgnoredAny>()?.is_some() {}
+        return Ok(());
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "requests" | "request" => map.next_value_seed(self)?,
+                _ => map.next_value::<de::IgnoredAny>().map(|_| ())?,
+            };
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+/// Collects the line each of a JSON or YAML script's rows starts on, in one pass. Serde
+/// only gives locations in errors; so a row's first scalar value fails, and it's caught.
+struct RowLines<'a>(&'a mut Vec<usize>);
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+impl<'de> de::DeserializeSeed<'de> for RowLines<'_> {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        return deserializer.deserialize_any(self);
+    }
+}
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+impl<'de> de::Visitor<'de> for RowLines<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "a list of rows, or a document");
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while seq.next_element_seed(RowLine(&mut *self.0))?.is_some() {}
+        return Ok(());
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "requests" | "request" => map.next_value_seed(RowLines(&mut *self.0))?,
+                _ => map.next_value::<de::IgnoredAny>().map(|_| ())?,
+            };
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+struct RowLine<'a>(&'a mut Vec<usize>);
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+impl<'de> de::DeserializeSeed<'de> for RowLine<'_> {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        return deserializer.deserialize_any(self);
+    }
+}
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+impl<'de> de::Visitor<'de> for RowLine<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "a row");
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut line = 0;
+        while map.next_key::<de::IgnoredAny>()?.is_some() {
+            if line != 0 {
+                map.next_value::<de::IgnoredAny>()?;
+                continue;
+            }
+            // A scalar's been read by the time it fails, so the rest of the row still can be.
+            if let Err(err) = map.next_value_seed(Scalar) {
+                line = error_line(&err.to_string());
+            }
+        }
+        self.0.push(line);
+        return Ok(());
+    }
+}
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+/// Fails on a scalar, so the error says where it is; anything else is skipped.
+struct Scalar;
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+impl<'de> de::DeserializeSeed<'de> for Scalar {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        return deserializer.deserialize_any(self);
+    }
+}
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+impl<'de> de::Visitor<'de> for Scalar {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "a value");
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<(), E> {
+        return Err(E::custom("row"));
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<(), E> {
+        return Err(E::custom("row"));
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<(), E> {
+        return Err(E::custom("row"));
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<(), E> {
+        return Err(E::custom("row"));
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> Result<(), E> {
+        return Err(E::custom("row"));
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        return Err(E::custom("row"));
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while seq.next_element::<de::IgnoredAny>()?.is_some() {}
+        return Ok(());
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while map
+            .next_entry::<de::IgnoredAny, de::IgnoredAny>()?
+            .is_some()
+        {}
+        return Ok(());
+    }
+}
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+/// Just the keys of a script's rows, and its document, for finding unknown ones.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScriptKeys {
+    Rows(Vec<collections::BTreeMap<String, de::IgnoredAny>>),
+    Document(DocumentKeys),
+}
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+#[derive(Deserialize)]
+struct DocumentKeys {
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    #[serde(default, alias = "request")]
+    pub requests: Vec<collections::BTreeMap<String, de::IgnoredAny>>,
+
+    #[serde(flatten)]
+    pub keys: collections::BTreeMap<String, de::IgnoredAny>,
+}
+
+/// Defaults and vars in effect while deserializing a script's rows.
+#[derive(Debug, Default, Clone)]
+struct Scope {
+    defaults: Defaults,
+    vars: collections::HashMap<String, String>,
+}
+
+impl Scope {
+    #[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+    fn merge(&self, defaults: Defaults, vars: collections::HashMap<String, Var>) -> Scope {
+        let mut scope = self.clone();
+
+        if !defaults.base_url.is_empty() {
+            scope.defaults.base_url = defaults.base_url;
+        }
+
+        if !defaults.headers.is_empty() {
+            if scope.defaults.headers.is_empty() {
+                scope.defaults.headers = defaults.headers;
+            } else {
+                scope.defaults.headers = format!("{};{}", scope.defaults.headers, defaults.headers);
+            }
+        }
+
+        if defaults.timeout != 0 {
+            scope.defaults.timeout = defaults.timeout;
+        }
+
+        if defaults.sleep != 0 {
+            scope.defaults.sleep = defaults.sleep;
+        }
+
+        for (name, value) in vars {
+            scope.vars.insert(name, value.to_string());
+        }
+
+        return scope;
+    }
+
+    /// Replaces '${name}' with the value of var 'name'.
+    fn expand(&self, value: &str) -> Result<String, ClientError> {
+        let mut expanded = String::new();
+        let mut rest = value;
+
+        while let Some(start) = rest.find("${") {
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => break,
+            };
+
+            let name = &rest[start + 2..end];
+            match self.vars.get(name) {
+                Some(var) => {
+                    expanded.push_str(&rest[..start]);
+                    expanded.push_str(var);
+                }
+                None => {
+                    return Err(ClientError::ConfigError(format!(
+                        "undefined variable '{}' in '{}'",
+                        name, value
+                    )))
+                }
+            }
+            rest = &rest[end + 1..];
+        }
+
+        expanded.push_str(rest);
+        return Ok(expanded);
+    }
+}
+
+impl Command {
+    /// Parses the command line; without a command, it's a run.
+    pub fn new() -> Result<Command, ClientError> {
+        let cli = Cli::parse();
+        let command = cli.command.unwrap_or(Command::Run(Box::new(cli.config)));
+
+        return match command {
+            Command::Run(config) => Ok(Command::Run(Box::new(config.setup()?))),
+            Command::Validate(config) => Ok(Command::Validate(Box::new(config.setup()?))),
+            Command::Convert(convert) => Ok(Command::Convert(ConvertConfig {
+                to: convert.to,
+                config: Box::new(convert.config.setup()?),
+            })),
+            Command::Coordinator(coordinator) => Ok(Command::Coordinator(CoordinatorConfig {
+                agents: coordinator.agents,
+                config: Box::new(coordinator.config.setup()?),
+            })),
+            _ => Ok(command),
+        };
+    }
+}
+
 impl Config {
-    pub fn new() -> Result<Self, ClientError> {
-        let config = Config::parse();
+    /// Settles options that follow from others, and checks they're valid.
+    fn setup(self) -> Result<Self, ClientError> {
+        let mut config = self;
 
-        if config.is_valid() {
-            return Ok(config);
+        if config.http2 {
+            config.http_version = "2".to_string();
         }
 
-        return Err(ClientError::ConfigError(format!(
-            "Configuration is invalid, see '--help' for details."
-        )));
+        // The dashboard takes over the terminal, so there's nowhere for other output to go.
+        if config.tui {
+            config.verbose = false;
+            config.debug = false;
+            config.errors = false;
+            config.report_interval = time::Duration::ZERO;
+        }
+
+        if !config.is_valid() {
+            return Err(ClientError::ConfigError(format!(
+                "Configuration is invalid, see '--help' for details."
+            )));
+        }
+
+        if config.seed == 0 {
+            config.seed = random::seed();
+        }
+
+        if !config.save_responses.is_empty() {
+            if let Err(err) = fs::create_dir_all(&config.save_responses) {
+                return Err(ClientError::ConfigError(format!(
+                    "invalid save responses path {}: {}",
+                    config.save_responses, err
+                )));
+            }
+        }
+
+        // Better to find out now than after a long run; leaving any past report as it was,
+        // and no file where there wasn't one, until the run's done.
+        if !config.report.is_empty() {
+            let existed = path::Path::new(&config.report).exists();
+            match fs::OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&config.report)
+            {
+                Ok(_) if !existed => {
+                    let _ = fs::remove_file(&config.report);
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    return Err(ClientError::ConfigError(format!(
+                        "invalid report path {}: {}",
+                        config.report, err
+                    )))
+                }
+            }
+        }
+
+        return Ok(config);
+    }
+
+    fn valid_outputs(&self) -> Vec<&str> {
+        return output::names();
+    }
+
+    fn valid_engines(&self) -> Vec<&str> {
+        #[allow(unused)]
+        let mut engines = vec!["threads"];
+
+        #[cfg(feature = "async")]
+        engines.push("async");
+
+        return engines;
+    }
+
+    fn valid_http_versions(&self) -> Vec<&str> {
+        #[allow(unused)]
+        let mut versions = vec!["1.1"];
+
+        // HTTP/2 is only supported by the async engine.
+        #[cfg(feature = "async")]
+        if self.engine == "async" {
+            versions.extend(["2", "h2c"]);
+        }
+
+        return versions;
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let o = self.valid_outputs();
+
+        if !self.valid_engines().contains(&self.engine.as_str()) {
+            return false;
+        }
+
+        if !self
+            .valid_http_versions()
+            .contains(&self.http_version.as_str())
+        {
+            return false;
+        }
+
+        if !self.replay.is_empty() && (self.endpoint.is_empty() || self.replay_speed < 0.0) {
+            return false;
+        }
+
+        if self.save_every == 0 {
+            return false;
+        }
+
+        // A mix is drawn a request at a time, so it's neither replayed nor looped through.
+        if self.mix && (!self.replay.is_empty() || self.users > 0) {
+            return false;
+        }
+
+        if !self.mix && (self.requests > 0 || !self.duration.is_zero()) {
+            return false;
+        }
+
+        // Replays keep their order, and a mix has none.
+        if self.shuffle && (self.mix || !self.replay.is_empty()) {
+            return false;
+        }
+
+        // Users make requests on threads of their own, and only they think.
+        if (self.users > 0 && self.engine != "threads")
+            || (self.users == 0 && !self.think_time.is_zero())
+        {
+            return false;
+        }
+
+        // Junit output's a single document; there's nowhere for interval reports.
+        if self.output == "junit" && !self.report_interval.is_zero() {
+            return false;
+        }
+
+        if self.compressed && !cfg!(feature = "compression") {
+            return false;
+        }
+
+        if self.tui && !cfg!(feature = "tui") {
+            return false;
+        }
+
+        if (!self.metrics_addr.is_empty() || !self.metrics_push.is_empty())
+            && !cfg!(feature = "prometheus")
+        {
+            return false;
+        }
+
+        return !(self.endpoint.is_empty() && self.script.is_empty() && self.summarize.is_empty())
+            && o.contains(&self.output.as_str());
+    }
+
+    /// Whether requests report when they start, for an in flight count.
+    pub fn tracks_in_flight(&self) -> bool {
+        return self.tui || !self.metrics_addr.is_empty();
+    }
+
+    /// Sleep ahead of each request; give or take up to '--sleep-jitter', as drawn.
+    pub fn sleep_time(&self) -> random::Distribution {
+        let sleep = time::Duration::from_millis(self.sleep);
+        if self.sleep_jitter == 0 {
+            return random::Distribution::Constant(sleep);
+        }
+
+        let jitter = time::Duration::from_millis(self.sleep_jitter);
+        return random::Distribution::Uniform(sleep.saturating_sub(jitter), sleep + jitter);
+    }
+
+    /// Blocks until this request's replay offset, scaled by '--replay-speed', has passed.
+    pub fn wait(&self, start: time::Instant) {
+        if let Some(delay) = self.delay(start) {
+            thread::sleep(delay);
+        }
+    }
+
+    /// Time remaining until this request's replay offset, if any.
+    pub fn delay(&self, start: time::Instant) -> Option<time::Duration> {
+        if self.offset == 0 || self.replay_speed == 0.0 {
+            return None;
+        }
+
+        let at = time::Duration::from_millis(self.offset).div_f64(self.replay_speed);
+        return at.checked_sub(start.elapsed()).filter(|d| !d.is_zero());
+    }
+
+    fn has_file(&self) -> bool {
+        if self.script.is_empty() {
+            return false;
+        }
+
+        if fs::metadata(self.script.clone()).is_err() {
+            return false;
+        }
+
+        return true;
+    }
+
+    /// A script's 'n'th row (from 0) as a request, noting where it came from; errors are
+    /// prefixed with that too.
+    fn from_record(
+        &self,
+        record: ConfigDeserializer,
+        scope: &Scope,
+        script: &str,
+        n: usize,
+    ) -> Result<Config, ClientError> {
+        let source = match record.line {
+            0 => format!("{}: request {}", script, n + 1),
+            line => format!("{}:{}", script, line),
+        };
+
+        let mut config = match self.deserialize(record, scope) {
+            Ok(config) => config,
+            Err(err) => return Err(ClientError::ConfigError(format!("{}: {}", source, err))),
+        };
+        config.source = source;
+        return Ok(config);
+    }
+
+    fn deserialize(
+        &self,
+        record: ConfigDeserializer,
+        scope: &Scope,
+    ) -> Result<Config, ClientError> {
+        let mut config: Config = self.clone();
+
+        if !scope.defaults.base_url.is_empty() {
+            config.endpoint = scope.expand(&scope.defaults.base_url)?;
+        }
+
+        config
+            .headers
+            .extend(split_headers(&scope.expand(&scope.defaults.headers)?));
+
+        if scope.defaults.timeout != 0 {
+            config.timeout = scope.defaults.timeout;
+        }
+
+        if scope.defaults.sleep != 0 {
+            config.sleep = scope.defaults.sleep;
+        }
+
+        if record.iterations != 0 {
+            config.iterations = record.iterations;
+        }
+
+        if !record.method.is_empty() {
+            config.method = scope.expand(&record.method)?;
+        }
+
+        if !record.endpoint.is_empty() {
+            let endpoint = scope.expand(&record.endpoint)?;
+            if endpoint.starts_with('/') {
+                config.endpoint = join_url(&config.endpoint, &endpoint);
+            } else {
+                config.endpoint = endpoint;
+            }
+        }
+
+        config
+            .headers
+            .extend(split_headers(&scope.expand(&record.headers)?));
+
+        if record.timeout != 0 {
+            config.timeout = record.timeout;
+        }
+
+        if record.sleep != 0 {
+            config.sleep = record.sleep;
+        }
+
+        if !record.think_time.is_empty() {
+            config.think_time = random::Distribution::parse(&record.think_time)
+                .map_err(|err| ClientError::ConfigError(format!("think_time: {}", err)))?;
+        }
+
+        if record.weight < 0.0 {
+            return Err(ClientError::ConfigError(format!(
+                "weight: {} is negative",
+                record.weight
+            )));
+        }
+        if record.weight != 0.0 {
+            config.weight = record.weight;
+        }
+
+        return Ok(config);
+    }
+
+    /// Loads a script file (following includes) into rows, layering its defaults and vars
+    /// over those of the including script. Rows that don't load are kept as errors, in
+    /// place, so the rest still load.
+    #[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+    fn from_script(
+        &self,
+        script: &str,
+        parent: &Scope,
+        depth: usize,
+    ) -> Result<Vec<Result<Config, ClientError>>, ClientError> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(ClientError::ConfigError(format!(
+                "too many nested includes at {}, is there a cycle?",
+                script
+            )));
+        }
+
+        let (scope, includes, records) = match read_script(script)? {
+            Script::Rows(records) => (parent.clone(), vec![], records),
+            Script::Document(document) => (
+                parent.merge(document.defaults, document.vars),
+                document.include,
+                document.requests,
+            ),
+        };
+
+        let mut configs = vec![];
+        for include in includes {
+            let include = match path::Path::new(script).parent() {
+                Some(dir) => dir.join(include).to_string_lossy().to_string(),
+                None => include,
+            };
+            match self.from_script(&include, &scope, depth + 1) {
+                Ok(rows) => configs.extend(rows),
+                Err(err) => configs.push(Err(err)),
+            }
+        }
+
+        let mut lines = None;
+        for (n, record) in records.into_iter().enumerate() {
+            let mut record = match record {
+                Ok(record) => record,
+                Err(err) => {
+                    configs.push(Err(err));
+                    continue;
+                }
+            };
+
+            let config = self.from_record(record.clone(), &scope, script, n);
+            if record.line != 0 || config.as_ref().is_ok_and(|c| c.lint().is_empty()) {
+                configs.push(config);
+                continue;
+            }
+
+            // Document rows are only located when there's a problem to report, as it takes
+            // parsing the script again; once, for all its rows.
+            let lines = lines.get_or_insert_with(|| row_lines(script));
+            record.line = lines.get(n).copied().unwrap_or_default();
+            configs.push(self.from_record(record, &scope, script, n));
+        }
+
+        return Ok(configs);
+    }
+
+    /// Streams JSON lines scripts one row at a time, rather than loading the whole file.
+    #[cfg(feature = "json")]
+    fn jsonl_rows(&self) -> Result<Rows, ClientError> {
+        let config = self.clone();
+        let scope = Scope::default();
+        let rows = jsonl_records(&self.script)?
+            .enumerate()
+            .map(move |(n, record)| match record {
+                Ok(record) => config.from_record(record, &scope, &config.script, n),
+                Err(err) => Err(err),
+            });
+
+        return Ok(Box::new(rows));
+    }
+
+    /// Streams pipe delimited scripts one row at a time, rather than loading the whole file.
+    fn csv_rows(&self) -> Result<Rows, ClientError> {
+        let config = self.clone();
+        let scope = Scope::default();
+        let rows = csv_stream(&self.script)?
+            .enumerate()
+            .map(move |(n, record)| match record {
+                Ok(record) => config.from_record(record, &scope, &config.script, n),
+                Err(err) => Err(err),
+            });
+
+        return Ok(Box::new(rows));
+    }
+
+    /// Lazily yields script rows; pipe delimited and JSON lines scripts, and access logs,
+    /// are streamed from disk, other formats are loaded up front as they're parsed as a
+    /// single document.
+    pub fn rows(&self) -> Result<Rows, ClientError> {
+        if !self.replay.is_empty() {
+            return replay::from_access_log(self);
+        }
+
+        if !self.has_file() {
+            return Ok(Box::new(iter::once(Ok(self.clone()))));
+        }
+
+        #[allow(unused)]
+        let ext = path_ext(&self.script).unwrap_or_default();
+
+        #[cfg(feature = "json")]
+        if ext == "jsonl" || ext == "ndjson" {
+            return self.jsonl_rows();
+        }
+
+        #[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+        if ["json", "yaml", "yml", "toml"].contains(&ext.as_str()) {
+            let configs = self.from_script(&self.script, &Scope::default(), 0)?;
+            return Ok(Box::new(configs.into_iter()));
+        }
+
+        return self.csv_rows();
+    }
+
+    #[cfg(test)]
+    pub fn to_vector(&self) -> Result<Vec<Config>, ClientError> {
+        return self.rows()?.collect();
+    }
+
+    /// Problems with a row that would otherwise only show once it's sent.
+    pub fn lint(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        match self.endpoint.split_once("://") {
+            Some(("http" | "https", rest)) if !rest.is_empty() && !rest.starts_with('/') => {}
+            _ => problems.push(format!("invalid endpoint '{}'", self.endpoint)),
+        }
+
+        if !METHODS.contains(&self.method.as_str()) {
+            problems.push(format!("unknown method '{}'", self.method));
+        }
+
+        for header in self.headers.iter().filter(|h| !h.is_empty()) {
+            if let Err(err) = header.clone().to_header() {
+                problems.push(err.to_string());
+            }
+        }
+
+        if self.iterations == 0 {
+            problems.push("no iterations".to_string());
+        }
+
+        return problems;
+    }
+
+    /// Loads and lints every row, without sending anything; carrying on past rows that
+    /// don't load, so every problem's found.
+    pub fn validate(&self) -> Validation {
+        let mut validation = Validation {
+            problems: self.unknown_columns(),
+            ..Default::default()
+        };
+
+        let rows = match self.rows() {
+            Ok(rows) => rows,
+            Err(err) => {
+                validation.problems.push(err.to_string());
+                return validation;
+            }
+        };
+
+        for row in rows {
+            let row = match row {
+                Ok(row) => row,
+                Err(err) => {
+                    validation.problems.push(err.to_string());
+                    continue;
+                }
+            };
+
+            validation.rows += 1;
+            validation.requests += row.iterations;
+            let source = match row.source.is_empty() {
+                true => format!("row {}", validation.rows),
+                false => row.source.clone(),
+            };
+            for problem in row.lint() {
+                validation.problems.push(format!("{}: {}", source, problem));
+            }
+        }
+        return validation;
     }
 
-    fn valid_outputs(&self) -> Vec<&str> {
+    /// Columns, or keys, in the script (and what it includes) that aren't loaded. Scripts
+    /// that don't parse are left to loading them to report.
+    fn unknown_columns(&self) -> Vec<String> {
+        if !self.replay.is_empty() || !self.has_file() {
+            return vec![];
+        }
+
         #[allow(unused)]
-        let mut outputs = vec!["default", "csv"];
+        let ext = path_ext(&self.script).unwrap_or_default();
 
         #[cfg(feature = "json")]
-        outputs.push("json");
+        if ext == "jsonl" || ext == "ndjson" {
+            return jsonl_unknown_keys(&self.script);
+        }
 
-        #[cfg(feature = "yaml")]
-        outputs.push("yaml");
+        #[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+        if ["json", "yaml", "yml", "toml"].contains(&ext.as_str()) {
+            return document_unknown_keys(&self.script, 0);
+        }
 
-        return outputs;
+        return csv_unknown_columns(&self.script);
     }
 
-    pub fn is_valid(&self) -> bool {
-        let o = self.valid_outputs();
+    /// Writes the script's rows, with defaults, vars and includes resolved, to another
+    /// script in the format of its extension; returns how many rows were written.
+    pub fn convert(&self, to: &str) -> Result<usize, ClientError> {
+        let records: Vec<ConfigDeserializer> = self
+            .rows()?
+            .map(|row| row.map(|row| row.to_record()))
+            .collect::<Result<_, _>>()?;
+
+        let error = |err: &dyn fmt::Display| ClientError::ConfigError(format!("{}: {}", to, err));
+        let body = match path_ext(to)?.as_str() {
+            "txt" | "csv" => {
+                let mut wtr = csv::WriterBuilder::new()
+                    .delimiter(b'|')
+                    .from_writer(vec![]);
+                for record in &records {
+                    wtr.serialize(record).map_err(|err| error(&err))?;
+                }
+                let body = wtr.into_inner().map_err(|err| error(&err))?;
+                String::from_utf8(body).map_err(|err| error(&err))?
+            }
+            #[cfg(feature = "json")]
+            "json" => serde_json::to_string_pretty(&records).map_err(|err| error(&err))? + "\n",
+            #[cfg(feature = "json")]
+            "jsonl" | "ndjson" => {
+                let mut body = String::new();
+                for record in &records {
+                    body.push_str(&serde_json::to_string(record).map_err(|err| error(&err))?);
+                    body.push('\n');
+                }
+                body
+            }
+            #[cfg(feature = "yaml")]
+            "yaml" | "yml" => serde_yaml::to_string(&records).map_err(|err| error(&err))?,
+            _ => {
+                return Err(ClientError::ConfigError(format!(
+                    "invalid script extension '{}'",
+                    to
+                )))
+            }
+        };
 
-        return !(self.endpoint.is_empty() && self.script.is_empty())
-            && o.contains(&self.output.as_str());
+        fs::write(to, body).map_err(|err| error(&err))?;
+        return Ok(records.len());
     }
 
-    pub fn sleep(&self) {
-        let sleep = std::time::Duration::from_millis(self.sleep);
-        if sleep > time::Duration::ZERO {
-            thread::sleep(sleep);
-        }
+    /// Back to a script row.
+    fn to_record(&self) -> ConfigDeserializer {
+        let headers: Vec<&str> = self
+            .headers
+            .iter()
+            .filter(|h| !h.is_empty())
+            .map(String::as_str)
+            .collect();
+
+        return ConfigDeserializer {
+            iterations: self.iterations,
+            method: self.method.clone(),
+            endpoint: self.endpoint.clone(),
+            headers: headers.join(";"),
+            timeout: self.timeout,
+            sleep: self.sleep,
+            think_time: match self.think_time.is_zero() {
+                true => String::new(),
+                false => self.think_time.to_string(),
+            },
+            weight: self.weight,
+            line: 0,
+        };
     }
+}
 
-    fn has_file(&self) -> bool {
-        if self.script.is_empty() {
-            return false;
+/// What 'Config::validate' found.
+#[derive(Debug, Default)]
+pub struct Validation {
+    pub rows: usize,
+    pub requests: usize,
+    pub problems: Vec<String>,
+}
+
+fn path_ext(script: &str) -> Result<String, ClientError> {
+    let extension = path::Path::new(script)
+        .extension()
+        .and_then(ffi::OsStr::to_str);
+    match extension {
+        Some(extension) => return Ok(extension.to_string()),
+        None => {
+            return Err(ClientError::ConfigError(
+                "invalid script extension".to_string(),
+            ))
         }
+    }
+}
 
-        if fs::metadata(self.script.clone()).is_err() {
-            return false;
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+fn script_body(script: &str) -> Result<String, ClientError> {
+    let content = fs::read_to_string(script);
+    match content {
+        Ok(content) => return Ok(content),
+        Err(_) => {
+            return Err(ClientError::ConfigError(format!(
+                "invalid script file path '{}'",
+                script
+            )))
         }
+    }
+}
 
-        return true;
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+/// Reads a script file into rows or a document, picking the format by extension and
+/// falling back to pipe delimited CSV.
+fn read_script(script: &str) -> Result<Script<Record>, ClientError> {
+    #[allow(unused)]
+    let ext = path_ext(script).unwrap_or_default();
+
+    #[cfg(feature = "yaml")]
+    if ext == "yaml" || ext == "yml" {
+        return document_records::<serde_yaml::Value>(script, &ext);
     }
 
-    #[cfg(any(feature = "json", feature = "yaml"))]
-    fn script_ext(&self) -> Result<String, ClientError> {
-        let extension = path::Path::new(&self.script)
-            .extension()
-            .and_then(ffi::OsStr::to_str);
-        match extension {
-            Some(extension) => return Ok(extension.to_string()),
-            None => {
-                return Err(ClientError::ConfigError(
-                    "invalid script extension".to_string(),
-                ))
-            }
-        }
+    #[cfg(feature = "json")]
+    if ext == "json" {
+        return document_records::<serde_json::Value>(script, &ext);
     }
 
-    fn script_body(&self) -> Result<String, ClientError> {
-        let script = self.script.clone();
-        let content = fs::read_to_string(script);
-        match content {
-            Ok(content) => return Ok(content),
-            Err(_) => {
-                return Err(ClientError::ConfigError(
-                    "invalid script file path".to_string(),
-                ))
-            }
-        }
+    #[cfg(feature = "json")]
+    if ext == "jsonl" || ext == "ndjson" {
+        return Ok(Script::Rows(jsonl_records(script)?.collect()));
     }
 
-    fn deserialize(&self, record: ConfigDeserializer) -> Config {
-        let mut config: Config = self.clone();
+    #[cfg(feature = "toml")]
+    if ext == "toml" {
+        return document_records::<toml::Value>(script, &ext);
+    }
 
-        if record.iterations != 0 {
-            config.iterations = record.iterations;
-        }
+    if ["json", "yaml", "yml"].contains(&ext.as_str()) {
+        return Err(ClientError::ConfigError(format!(
+            "invalid script extension '{}'",
+            script
+        )));
+    }
 
-        if !record.method.is_empty() {
-            config.method = record.method;
-        }
+    return csv_records(script);
+}
 
-        if !record.endpoint.is_empty() {
-            config.endpoint = record.endpoint;
-        }
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+/// Reads a json, yaml or toml script with its rows as values of the format, 'V'; then
+/// deserializes each row on its own.
+fn document_records<V>(script: &str, ext: &str) -> Result<Script<Record>, ClientError>
+where
+    V: de::DeserializeOwned + de::Deserializer<'static>,
+{
+    let records = |rows: Vec<V>| -> Vec<Record> {
+        return rows
+            .into_iter()
+            .enumerate()
+            .map(|(n, row)| {
+                return de::Deserialize::deserialize(row).map_err(|err| row_error(script, n, &err));
+            })
+            .collect();
+    };
 
-        if !record.headers.is_empty() {
-            config.endpoint = record.headers;
-        }
+    return match parse_document::<Script<V>>(script, ext)? {
+        Script::Rows(rows) => Ok(Script::Rows(records(rows))),
+        Script::Document(document) => Ok(Script::Document(ScriptDocument {
+            defaults: document.defaults,
+            vars: document.vars,
+            include: document.include,
+            requests: records(document.requests),
+        })),
+    };
+}
 
-        if record.sleep != 0 {
-            config.sleep = record.sleep;
-        }
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+/// Parses a json, yaml or toml script, with errors located by line and column.
+fn parse_document<T: de::DeserializeOwned>(script: &str, ext: &str) -> Result<T, ClientError> {
+    let body = script_body(script)?;
+
+    return deserialize_document(&body, ext, marker::PhantomData).map_err(|(at, err)| match at {
+        Some((line, column)) => located(script, line, column, &err),
+        None => ClientError::ConfigError(format!("{}: {}", script, err)),
+    });
+}
 
-        return config;
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+/// Deserializes a json, yaml or toml script's body with 'seed'; errors have the line and
+/// column they're at, where the format gives one.
+fn deserialize_document<T, S>(
+    body: &str,
+    ext: &str,
+    seed: S,
+) -> Result<T, (Option<(usize, usize)>, String)>
+where
+    S: for<'de> de::DeserializeSeed<'de, Value = T>,
+{
+    #[cfg(feature = "yaml")]
+    if ext == "yaml" || ext == "yml" {
+        return seed
+            .deserialize(serde_yaml::Deserializer::from_str(body))
+            .map_err(|err| {
+                let at = err.location().map(|at| (at.line(), at.column()));
+                return (at, err.to_string());
+            });
     }
 
-    fn from_csv(&self) -> Result<Vec<Config>, ClientError> {
-        let script_body = self.script_body()?;
+    #[cfg(feature = "json")]
+    if ext == "json" {
+        let mut deserializer = serde_json::Deserializer::from_str(body);
+        return seed
+            .deserialize(&mut deserializer)
+            .and_then(|value| deserializer.end().map(|_| value))
+            .map_err(|err| (Some((err.line(), err.column())), err.to_string()));
+    }
 
-        let mut reader = csv::ReaderBuilder::new()
-            .delimiter(b'|')
-            .from_reader(script_body.as_bytes());
+    #[cfg(feature = "toml")]
+    if ext == "toml" {
+        return seed
+            .deserialize(toml::Deserializer::new(body))
+            .map_err(|err| {
+                let at = err.span().map(|span| {
+                    let before = &body[..span.start];
+                    let line = before.matches('\n').count() + 1;
+                    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+                    return (line, column);
+                });
+                return (at, err.message().to_string());
+            });
+    }
 
-        let mut configs: Vec<Config> = vec![];
-        for record in reader.deserialize() {
-            if record.is_err() {
-                return Err(ClientError::ConfigError(record.unwrap_err().to_string()));
-            }
+    return Err((None, "invalid script extension".to_string()));
+}
 
-            let config: Config = self.deserialize(record.unwrap());
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+/// Why request 'n' of a script doesn't deserialize; located by deserializing it again, in
+/// place, falling back to 'err'.
+fn row_error(script: &str, n: usize, err: &dyn fmt::Display) -> ClientError {
+    let ext = path_ext(script).unwrap_or_default();
 
-            configs.push(config);
-        }
+    return match script_body(script).map(|body| deserialize_document(&body, &ext, RowAt(n))) {
+        Ok(Err((Some((line, column)), err))) => located(script, line, column, &err),
+        _ => ClientError::ConfigError(format!("{}: request {}: {}", script, n + 1, err)),
+    };
+}
 
-        return Ok(configs);
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+/// Lines a script's rows start on, by row; 0, or missing, where it can't tell.
+fn row_lines(script: &str) -> Vec<usize> {
+    let ext = path_ext(script).unwrap_or_default();
+    let body = match script_body(script) {
+        Ok(body) => body,
+        Err(_) => return vec![],
+    };
+
+    // TOML errors caught part way don't say where they are; but its rows have spans.
+    #[cfg(feature = "toml")]
+    if ext == "toml" {
+        let rows = match toml::from_str::<Script<toml::Spanned<de::IgnoredAny>>>(&body) {
+            Ok(Script::Rows(rows)) => rows,
+            Ok(Script::Document(document)) => document.requests,
+            Err(_) => vec![],
+        };
+        return rows
+            .iter()
+            .map(|row| body[..row.span().start].matches('\n').count() + 1)
+            .collect();
     }
 
-    #[cfg(feature = "yaml")]
-    fn from_yaml(&self) -> Result<Vec<Config>, ClientError> {
-        let script_body = self.script_body()?;
+    let mut lines = vec![];
+    let _ = deserialize_document(&body, &ext, RowLines(&mut lines));
+    return lines;
+}
+
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+/// Line an error's at, from its message; e.g. '... at line 3 column 5'.
+fn error_line(message: &str) -> usize {
+    return match message.find("at line ") {
+        Some(at) => message[at + 8..]
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect::<String>()
+            .parse()
+            .unwrap_or_default(),
+        None => 0,
+    };
+}
 
-        let mut configs: Vec<Config> = vec![];
+/// Unknown columns in a pipe delimited script's header.
+fn csv_unknown_columns(script: &str) -> Vec<String> {
+    let headers = fs::File::open(script).ok().and_then(|file| {
+        let mut reader = csv::ReaderBuilder::new().delimiter(b'|').from_reader(file);
+        return reader.headers().ok().cloned();
+    });
+
+    return headers
+        .iter()
+        .flatten()
+        .filter(|column| !ROW_KEYS.contains(column))
+        .map(|column| format!("{}:1: unknown column '{}'", script, column))
+        .collect();
+}
 
-        let expect = format!("Bad YAML from {}", self.script);
-        let records: Vec<ConfigDeserializer> = serde_yaml::from_str(&script_body).expect(&expect);
+#[cfg(feature = "json")]
+/// Checks a JSON lines script's keys a line at a time, as its rows are streamed.
+fn jsonl_unknown_keys(script: &str) -> Vec<String> {
+    let file = match fs::File::open(script) {
+        Ok(file) => file,
+        Err(_) => return vec![],
+    };
 
-        for record in records {
-            configs.push(self.deserialize(record));
+    let mut problems = vec![];
+    for (i, line) in io::BufReader::new(file).lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let keys: collections::BTreeMap<String, de::IgnoredAny> = match serde_json::from_str(&line)
+        {
+            Ok(keys) => keys,
+            Err(_) => continue,
+        };
+        for key in keys.keys().filter(|key| !ROW_KEYS.contains(&key.as_str())) {
+            problems.push(format!("{}:{}: unknown key '{}'", script, i + 1, key));
         }
+    }
+    return problems;
+}
 
-        return Ok(configs);
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+fn document_unknown_keys(script: &str, depth: usize) -> Vec<String> {
+    #[allow(unused)]
+    let ext = path_ext(script).unwrap_or_default();
+    if depth > MAX_INCLUDE_DEPTH {
+        return vec![];
     }
 
     #[cfg(feature = "json")]
-    fn from_json(&self) -> Result<Vec<Config>, ClientError> {
-        let script_body = self.script_body()?;
+    if ext == "jsonl" || ext == "ndjson" {
+        return jsonl_unknown_keys(script);
+    }
+
+    if !["json", "yaml", "yml", "toml"].contains(&ext.as_str()) {
+        return csv_unknown_columns(script);
+    }
 
-        let mut configs: Vec<Config> = vec![];
+    let (rows, keys, includes) = match parse_document(script, &ext) {
+        Ok(ScriptKeys::Rows(rows)) => (rows, collections::BTreeMap::new(), vec![]),
+        Ok(ScriptKeys::Document(document)) => (document.requests, document.keys, document.include),
+        Err(_) => return vec![],
+    };
+
+    let mut problems = vec![];
+    let mut lines = None;
+    for key in keys
+        .keys()
+        .filter(|key| !DOCUMENT_KEYS.contains(&key.as_str()))
+    {
+        problems.push(format!("{}: unknown key '{}'", script, key));
+    }
 
-        let expect = format!("Bad JSON from {}", self.script);
-        let records: Vec<ConfigDeserializer> = serde_json::from_str(&script_body).expect(&expect);
+    for include in includes {
+        let include = match path::Path::new(script).parent() {
+            Some(dir) => dir.join(include).to_string_lossy().to_string(),
+            None => include,
+        };
+        problems.extend(document_unknown_keys(&include, depth + 1));
+    }
 
-        for record in records {
-            configs.push(self.deserialize(record));
+    for (n, row) in rows.iter().enumerate() {
+        let unknown: Vec<&String> = row
+            .keys()
+            .filter(|key| !ROW_KEYS.contains(&key.as_str()))
+            .collect();
+        if unknown.is_empty() {
+            continue;
         }
 
-        return Ok(configs);
+        let lines = lines.get_or_insert_with(|| row_lines(script));
+        let source = match lines.get(n).copied().unwrap_or_default() {
+            0 => format!("{}: request {}", script, n + 1),
+            line => format!("{}:{}", script, line),
+        };
+        for key in unknown {
+            problems.push(format!("{}: unknown key '{}'", source, key));
+        }
     }
+    return problems;
+}
 
-    pub fn to_vector(&self) -> Result<Vec<Config>, ClientError> {
-        let mut configs: Vec<Config> = vec![];
+/// An error at a line and column of a script; less the location serde puts at the end.
+fn located(script: &str, line: usize, column: usize, err: &dyn fmt::Display) -> ClientError {
+    let message = err.to_string();
+    let message = match message.rfind(" at line ") {
+        Some(at) => &message[..at],
+        None => &message,
+    };
+    return ClientError::ConfigError(format!("{}:{}:{}: {}", script, line, column, message));
+}
 
-        if !self.has_file() {
-            configs.push(self.clone());
-            return Ok(configs);
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+fn csv_records(script: &str) -> Result<Script<Record>, ClientError> {
+    return Ok(Script::Rows(csv_stream(script)?.collect()));
+}
+
+fn csv_stream(
+    script: &str,
+) -> Result<impl Iterator<Item = Result<ConfigDeserializer, ClientError>>, ClientError> {
+    let file = match fs::File::open(script) {
+        Ok(file) => file,
+        Err(_) => {
+            return Err(ClientError::ConfigError(format!(
+                "invalid script file path '{}'",
+                script
+            )))
         }
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b'|')
+        .from_reader(io::BufReader::new(file));
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(err) => return Err(csv_error(script, None, &err)),
+    };
 
-        #[cfg(feature = "yaml")]
-        if self.script_ext()? == "yaml".to_string() {
-            return self.from_yaml();
+    let script = script.to_string();
+    let records = reader.into_records().map(move |record| {
+        let record = record.map_err(|err| csv_error(&script, None, &err))?;
+        let mut row: ConfigDeserializer = record
+            .deserialize(Some(&headers))
+            .map_err(|err| csv_error(&script, Some((&headers, &record)), &err))?;
+        row.line = record.position().map(|at| at.line()).unwrap_or_default() as usize;
+        return Ok(row);
+    });
+
+    return Ok(records);
+}
+
+/// A pipe delimited script error, by line; and column, when it's down to a field of
+/// 'record'.
+fn csv_error(
+    script: &str,
+    record: Option<(&csv::StringRecord, &csv::StringRecord)>,
+    err: &csv::Error,
+) -> ClientError {
+    let line = err.position().map(|at| at.line()).unwrap_or_default() as usize;
+    match (err.kind(), record) {
+        (csv::ErrorKind::Deserialize { err, .. }, Some((headers, record))) => {
+            if let Some(field) = err.field().map(|field| field as usize) {
+                let column = record
+                    .iter()
+                    .take(field)
+                    .map(|value| value.chars().count() + 1)
+                    .sum::<usize>()
+                    + 1;
+                let name = headers.get(field).unwrap_or_default();
+                return located(script, line, column, &format!("{}: {}", name, err.kind()));
+            }
+            return ClientError::ConfigError(format!("{}:{}: {}", script, line, err.kind()));
+        }
+        (
+            csv::ErrorKind::UnequalLengths {
+                expected_len, len, ..
+            },
+            _,
+        ) => {
+            return ClientError::ConfigError(format!(
+                "{}:{}: {} columns, where the header has {}",
+                script, line, len, expected_len
+            ));
         }
+        _ => return ClientError::ConfigError(format!("{}:{}: {}", script, line, err)),
+    }
+}
 
-        #[cfg(feature = "json")]
-        if self.script_ext()? == "json".to_string() {
-            return self.from_json();
+#[cfg(feature = "json")]
+fn jsonl_records(
+    script: &str,
+) -> Result<impl Iterator<Item = Result<ConfigDeserializer, ClientError>>, ClientError> {
+    let file = match fs::File::open(script) {
+        Ok(file) => file,
+        Err(_) => {
+            return Err(ClientError::ConfigError(format!(
+                "invalid script file path '{}'",
+                script
+            )))
         }
+    };
+
+    let script = script.to_string();
+
+    let records = io::BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter_map(move |(i, line)| {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    return Some(Err(ClientError::ConfigError(format!(
+                        "{}:{}: {}",
+                        script,
+                        i + 1,
+                        err
+                    ))))
+                }
+            };
+
+            if line.trim().is_empty() {
+                return None;
+            }
+
+            match serde_json::from_str::<ConfigDeserializer>(&line) {
+                Ok(mut record) => {
+                    record.line = i + 1;
+                    Some(Ok(record))
+                }
+                Err(err) => Some(Err(located(&script, i + 1, err.column(), &err))),
+            }
+        });
+
+    return Ok(records);
+}
+
+/// Splits ';' delimited script headers, e.g. 'User-Agent:noop-client;X-Test:run1'.
+fn split_headers(headers: &str) -> Vec<String> {
+    return headers
+        .split(';')
+        .map(str::trim)
+        .filter(|h| !h.is_empty())
+        .map(str::to_string)
+        .collect();
+}
 
-        return self.from_csv();
+fn join_url(base: &str, endpoint: &str) -> String {
+    if base.is_empty() {
+        return endpoint.to_string();
     }
+
+    return format!(
+        "{}/{}",
+        base.trim_end_matches('/'),
+        endpoint.trim_start_matches('/')
+    );
 }
 
 // ---
-// For some reason this doesn't show as being used, even though it is.
-#[allow(unused)]
-fn test_config() -> Config {
+#[cfg(test)]
+pub fn test_config() -> Config {
     Config {
         endpoint: "http://www.example.com".to_string(),
         method: "GET".to_string(),
         headers: vec!["foo=bar".to_string()],
         script: "".to_string(),
         sleep: 0,
+        sleep_jitter: 0,
+        think_time: random::Distribution::Constant(time::Duration::ZERO),
         verbose: false,
         debug: false,
         errors: false,
         iterations: 1,
         pool_size: 1,
+        users: 0,
+        loops: 1,
+        mix: false,
+        requests: 0,
+        duration: time::Duration::ZERO,
+        shuffle: false,
+        seed: 0,
+        weight: 1.0,
         output: "default".to_string(),
+        timeout: 0,
+        engine: "threads".to_string(),
+        http_version: "1.1".to_string(),
+        http2: false,
+        max_streams: 0,
+        replay: "".to_string(),
+        replay_speed: 1.0,
+        compressed: false,
+        save_responses: "".to_string(),
+        save_every: 1,
+        report_interval: time::Duration::ZERO,
+        offset: 0,
+        row: 0,
+        source: "".to_string(),
+        tui: false,
+        metrics_addr: "".to_string(),
+        metrics_push: "".to_string(),
+        statsd: "".to_string(),
+        statsd_prefix: "noop_client".to_string(),
+        influx: "".to_string(),
+        results_file: "".to_string(),
+        summarize: "".to_string(),
+        report: "".to_string(),
+        check: false,
+        dry_run: false,
     }
 }
 
@@ -310,11 +1943,122 @@ fn is_valid_test() {
     let mut c = test_config();
     assert!(c.is_valid());
 
+    c.engine = "carrier-pigeon".to_string();
+    assert!(!c.is_valid());
+    c.engine = "threads".to_string();
+
+    c.http_version = "2".to_string();
+    assert!(!c.is_valid()); // HTTP/2 requires the async engine
+
+    #[cfg(feature = "async")]
+    {
+        c.engine = "async".to_string();
+        assert!(c.is_valid());
+        c.engine = "threads".to_string();
+    }
+    c.http_version = "1.1".to_string();
+
     c.endpoint = String::new();
     assert!(!c.is_valid());
 
     c.script = "file.txt".to_string();
     assert!(c.is_valid());
+
+    c.replay = "access.log".to_string();
+    assert!(!c.is_valid()); // replay requires an endpoint
+
+    c.endpoint = "http://localhost:3000".to_string();
+    assert!(c.is_valid());
+
+    c.replay_speed = -1.0;
+    assert!(!c.is_valid());
+    c.replay_speed = 1.0;
+
+    c.save_every = 0;
+    assert!(!c.is_valid());
+    c.save_every = 1;
+
+    c.output = "junit".to_string();
+    assert!(c.is_valid());
+    c.report_interval = time::Duration::from_secs(1);
+    assert!(!c.is_valid());
+    c.output = "default".to_string();
+    assert!(c.is_valid());
+    c.report_interval = time::Duration::ZERO;
+
+    c.compressed = true;
+    assert_eq!(c.is_valid(), cfg!(feature = "compression"));
+    c.compressed = false;
+
+    c.think_time = random::Distribution::Constant(time::Duration::from_millis(200));
+    assert!(!c.is_valid()); // only users think
+    c.users = 10;
+    assert!(c.is_valid());
+    c.engine = "async".to_string();
+    assert!(!c.is_valid());
+    c.engine = "threads".to_string();
+    c.users = 0;
+    c.think_time = random::Distribution::Constant(time::Duration::ZERO);
+
+    c.requests = 100;
+    assert!(!c.is_valid()); // only a mix draws requests
+    c.mix = true;
+    assert!(!c.is_valid()); // replays aren't drawn from
+    c.replay = "".to_string();
+    assert!(c.is_valid());
+    c.users = 1;
+    assert!(!c.is_valid());
+    c.users = 0;
+    c.shuffle = true;
+    assert!(!c.is_valid()); // a mix has no order
+    c.mix = false;
+    c.requests = 0;
+    assert!(c.is_valid());
+    c.replay = "access.log".to_string();
+    assert!(!c.is_valid()); // replays keep theirs
+    c.shuffle = false;
+
+    c.tui = true;
+    assert_eq!(c.is_valid(), cfg!(feature = "tui"));
+    c.tui = false;
+    c.metrics_addr = "127.0.0.1:9090".to_string();
+    assert_eq!(c.is_valid(), cfg!(feature = "prometheus"));
+    c.metrics_addr = "".to_string();
+    c.metrics_push = "http://localhost:9091/metrics/job/noop-client".to_string();
+    assert_eq!(c.is_valid(), cfg!(feature = "prometheus"));
+}
+
+#[test]
+fn setup_report_test() {
+    let path = std::env::temp_dir().join(format!("noop-client-setup-{}.html", std::process::id()));
+    let mut c = test_config();
+    c.report = path.to_str().unwrap().to_string();
+
+    let _ = fs::remove_file(&path);
+    assert!(c.clone().setup().is_ok());
+    assert!(!path.exists());
+
+    fs::write(&path, "past").unwrap();
+    assert!(c.clone().setup().is_ok());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "past");
+    let _ = fs::remove_file(&path);
+
+    c.report = "/no/such/dir/report.html".to_string();
+    assert!(c.setup().is_err());
+}
+
+#[test]
+fn parse_duration_test() {
+    assert_eq!(parse_duration("5s"), Ok(time::Duration::from_secs(5)));
+    assert_eq!(parse_duration("5"), Ok(time::Duration::from_secs(5)));
+    assert_eq!(
+        parse_duration("250ms"),
+        Ok(time::Duration::from_millis(250))
+    );
+    assert_eq!(parse_duration("1.5m"), Ok(time::Duration::from_secs(90)));
+    assert_eq!(parse_duration("1h"), Ok(time::Duration::from_secs(3600)));
+    assert!(parse_duration("5d").is_err());
+    assert!(parse_duration("s").is_err());
 }
 
 #[test]
@@ -349,6 +2093,18 @@ fn has_file_test() {
     assert!(c.has_file()); // with valid file
 }
 
+#[test]
+fn rows_test() {
+    let mut c = test_config();
+    c.script = "test/test_script.txt".to_string();
+
+    let mut rows = c.rows().unwrap();
+    let first = rows.next().unwrap().unwrap();
+    assert_eq!(first.iterations, 6);
+    assert_eq!(first.endpoint, "http://localhost:3000/request1".to_string());
+    assert_eq!(rows.count(), 5);
+}
+
 #[test]
 fn to_vector_test() {
     let c = test_config();
@@ -362,6 +2118,41 @@ fn to_vector_test() {
     assert_eq!(v[0].method, "GET".to_string());
 }
 
+#[test]
+fn wait_test() {
+    let mut c = test_config();
+    let start = time::Instant::now();
+
+    c.offset = 50;
+    c.replay_speed = 0.0;
+    c.wait(start);
+    assert!(start.elapsed() < time::Duration::from_millis(50));
+
+    c.replay_speed = 2.0;
+    c.wait(start);
+    assert!(start.elapsed() >= time::Duration::from_millis(25));
+}
+
+#[test]
+fn sleep_time_test() {
+    let ms = time::Duration::from_millis;
+    let mut c = test_config();
+    c.sleep = 100;
+    assert_eq!(c.sleep_time(), random::Distribution::Constant(ms(100)));
+
+    c.sleep_jitter = 20;
+    assert_eq!(
+        c.sleep_time(),
+        random::Distribution::Uniform(ms(80), ms(120))
+    );
+
+    c.sleep_jitter = 150;
+    assert_eq!(
+        c.sleep_time(),
+        random::Distribution::Uniform(ms(0), ms(250))
+    );
+}
+
 #[test]
 fn verbose_test() {
     let mut c = test_config();
@@ -375,13 +2166,12 @@ fn verbose_test() {
 }
 
 #[test]
-#[cfg(any(feature = "json", feature = "yaml"))]
-fn script_ext_test() {
+fn path_ext_test() {
     let mut c = test_config();
-    assert!(c.script_ext().is_err());
+    assert!(path_ext(&c.script).is_err());
 
     c.script = "/foo.json".to_string();
-    assert_eq!(c.script_ext().unwrap(), "json");
+    assert_eq!(path_ext(&c.script).unwrap(), "json");
 }
 
 #[test]
@@ -391,13 +2181,446 @@ fn deserialize_test() {
         iterations: 5,
         method: "GET".to_string(),
         endpoint: "https://www.example.com/".to_string(),
-        headers: String::new(),
+        headers: "X-Foo:bar;X-Bah:boo".to_string(),
+        timeout: 0,
         sleep: 0,
+        think_time: "".to_string(),
+        weight: 0.0,
+        line: 0,
     };
 
-    let c = cfg.deserialize(r);
+    let c = cfg.deserialize(r, &Scope::default()).unwrap();
 
     assert_eq!(c.iterations, 5);
     assert_eq!(c.method, "GET".to_string());
     assert_eq!(c.endpoint, "https://www.example.com/".to_string());
+    assert_eq!(c.headers, vec!["foo=bar", "X-Foo:bar", "X-Bah:boo"]);
+}
+
+#[test]
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+fn deserialize_scope_test() {
+    let cfg = test_config();
+    let mut vars = collections::HashMap::new();
+    vars.insert("run".to_string(), Var::Integer(2));
+
+    let defaults = Defaults {
+        base_url: "http://localhost:3000/".to_string(),
+        headers: "User-Agent:noop-client".to_string(),
+        timeout: 500,
+        sleep: 10,
+    };
+    let scope = Scope::default().merge(defaults, vars);
+
+    let r = ConfigDeserializer {
+        endpoint: "/request${run}".to_string(),
+        headers: "X-Test:run${run}".to_string(),
+        sleep: 20,
+        ..Default::default()
+    };
+    let c = cfg.deserialize(r, &scope).unwrap();
+    assert_eq!(c.endpoint, "http://localhost:3000/request2".to_string());
+    assert_eq!(
+        c.headers,
+        vec!["foo=bar", "User-Agent:noop-client", "X-Test:run2"]
+    );
+    assert_eq!(c.timeout, 500);
+    assert_eq!(c.sleep, 20);
+
+    let r = ConfigDeserializer {
+        endpoint: "/${missing}".to_string(),
+        ..Default::default()
+    };
+    assert!(cfg.deserialize(r, &scope).is_err());
+}
+
+#[test]
+fn expand_test() {
+    let mut scope = Scope::default();
+    scope.vars.insert("a".to_string(), "1".to_string());
+
+    assert_eq!(scope.expand("x${a}y${a}").unwrap(), "x1y1".to_string());
+    assert_eq!(scope.expand("no vars").unwrap(), "no vars".to_string());
+    assert_eq!(scope.expand("${a").unwrap(), "${a".to_string());
+    assert!(scope.expand("${b}").is_err());
+}
+
+#[test]
+#[cfg(feature = "yaml")]
+fn from_script_document_test() {
+    let mut c = test_config();
+    c.script = "test/test_script_defaults.yaml".to_string();
+
+    let v = c.to_vector().unwrap();
+    assert_eq!(v.len(), 8); // 6 included + 2 own rows
+
+    // included pipe delimited rows pick up defaults, but keep their own endpoints
+    assert_eq!(v[0].endpoint, "http://localhost:3000/request1".to_string());
+    assert!(v[0].headers.contains(&"User-Agent:noop-client".to_string()));
+    assert_eq!(v[0].timeout, 1000);
+
+    assert_eq!(
+        v[6].endpoint,
+        "http://localhost:3000/defaults/run7".to_string()
+    );
+    assert!(v[6].headers.contains(&"X-Test:run7".to_string()));
+    assert_eq!(v[6].sleep, 5);
+    assert_eq!(v[7].endpoint, "http://localhost:3000".to_string());
+}
+
+#[test]
+#[cfg(feature = "toml")]
+fn from_toml_test() {
+    let mut c = test_config();
+    c.script = "test/test_script.toml".to_string();
+
+    let v = c.to_vector().unwrap();
+    assert_eq!(v.len(), 6);
+    assert_eq!(v[0].iterations, 6);
+    assert_eq!(v[1].method, "POST".to_string());
+    assert_eq!(v[4].endpoint, "http://localhost:3000/request5".to_string());
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn from_jsonl_test() {
+    let mut c = test_config();
+    c.script = "test/test_script.jsonl".to_string();
+
+    let v = c.to_vector().unwrap();
+    assert_eq!(v.len(), 6);
+    assert_eq!(v[0].iterations, 6);
+    assert_eq!(v[2].method, "DELETE".to_string());
+
+    c.script = "test/test_script.json".to_string();
+    let err = c.jsonl_rows().unwrap().next().unwrap();
+    assert!(err.is_err());
+    assert!(err
+        .unwrap_err()
+        .to_string()
+        .starts_with("test/test_script.json:1:"));
+}
+
+#[test]
+fn command_test() {
+    let cli = Cli::try_parse_from(["noop-client", "-e", "http://localhost:3000/"]).unwrap();
+    assert!(cli.command.is_none());
+    assert_eq!(cli.config.endpoint, "http://localhost:3000/".to_string());
+
+    let cli = Cli::try_parse_from(["noop-client", "run", "-e", "http://localhost:3000/"]).unwrap();
+    match cli.command {
+        Some(Command::Run(config)) => assert_eq!(config.endpoint, "http://localhost:3000/"),
+        _ => panic!("expected run"),
+    }
+
+    let cli = Cli::try_parse_from(["noop-client", "convert", "out.yaml", "-f", "in.txt"]).unwrap();
+    match cli.command {
+        Some(Command::Convert(convert)) => {
+            assert_eq!(convert.to, "out.yaml");
+            assert_eq!(convert.config.script, "in.txt");
+        }
+        _ => panic!("expected convert"),
+    }
+
+    let cli = Cli::try_parse_from(["noop-client", "report", "results.csv", "-o", "json"]).unwrap();
+    assert!(matches!(cli.command, Some(Command::Report(report)) if report.output == "json"));
+
+    // A run's options go after 'run'.
+    assert!(Cli::try_parse_from(["noop-client", "-e", "http://localhost:3000/", "run"]).is_err());
+}
+
+#[test]
+fn lint_test() {
+    let mut c = test_config();
+    c.headers = vec!["".to_string(), "X-Test:run1".to_string()];
+    assert!(c.lint().is_empty());
+
+    c.endpoint = "localhost:3000".to_string();
+    c.method = "GTE".to_string();
+    c.headers = vec!["X-Test".to_string()];
+    c.iterations = 0;
+    assert_eq!(
+        c.lint(),
+        vec![
+            "invalid endpoint 'localhost:3000'".to_string(),
+            "unknown method 'GTE'".to_string(),
+            "Header values cannot be empty in 'X-Test'".to_string(),
+            "no iterations".to_string(),
+        ]
+    );
+
+    c.endpoint = "https:///request1".to_string();
+    assert!(c.lint()[0].starts_with("invalid endpoint"));
+}
+
+#[test]
+fn validate_test() {
+    let mut c = test_config();
+    c.script = "test/test_script.txt".to_string();
+
+    let validation = c.validate();
+    assert_eq!(validation.rows, 6);
+    assert_eq!(validation.requests, 11);
+    assert_eq!(
+        validation.problems,
+        vec!["test/test_script.txt:7: invalid endpoint 'bad_endpoint'".to_string()]
+    );
+
+    // Carries on past rows that don't load.
+    c.script = test_script(
+        "validate.txt",
+        "iterations|method|endpoint|sleeps\nx|GET|http://localhost:3000/|1\n1|GTE|http://localhost:3000/|1\n1|GET\n",
+    );
+    let validation = c.validate();
+    assert_eq!(validation.rows, 1);
+    assert_eq!(
+        validation.problems,
+        vec![
+            format!("{}:1: unknown column 'sleeps'", c.script),
+            format!(
+                "{}:2:1: iterations: invalid digit found in string",
+                c.script
+            ),
+            format!("{}:3: unknown method 'GTE'", c.script),
+            format!("{}:4: 2 columns, where the header has 4", c.script),
+        ]
+    );
+    let _ = fs::remove_file(&c.script);
+}
+
+#[test]
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+fn validate_document_test() {
+    let mut scripts = vec![];
+    if cfg!(feature = "yaml") {
+        scripts.push((
+            "validate.yaml",
+            "requests:\n  - endpoint: bad_a\n  - iterations: x\n  - endpoint: bad_b\n",
+            [
+                ":2: invalid endpoint 'bad_a'",
+                ":3:17: ",
+                ":4: invalid endpoint 'bad_b'",
+            ],
+        ));
+    }
+    if cfg!(feature = "json") {
+        scripts.push((
+            "validate.json",
+            "[\n  {\"endpoint\": \"bad_a\"},\n  {\"iterations\": \"x\"},\n  {\"endpoint\": \"bad_b\"}\n]",
+            [":2: invalid endpoint 'bad_a'", ":3:", ":4: invalid endpoint 'bad_b'"],
+        ));
+    }
+    if cfg!(feature = "toml") {
+        scripts.push((
+            "validate.toml",
+            "[[request]]\nendpoint = \"bad_a\"\n\n[[request]]\niterations = \"x\"\n\n[[request]]\nendpoint = \"bad_b\"\n",
+            [":1: invalid endpoint 'bad_a'", ":5:", ":7: invalid endpoint 'bad_b'"],
+        ));
+    }
+
+    let mut c = test_config();
+    for (name, body, problems) in scripts {
+        c.script = test_script(name, body);
+        let validation = c.validate();
+        assert_eq!(validation.rows, 2, "{}", name);
+        assert_eq!(
+            validation.problems.len(),
+            3,
+            "{}: {:?}",
+            name,
+            validation.problems
+        );
+        for (problem, at) in validation.problems.iter().zip(problems) {
+            assert!(
+                problem.starts_with(&format!("{}{}", c.script, at)),
+                "{}: {}",
+                name,
+                problem
+            );
+        }
+        let _ = fs::remove_file(&c.script);
+    }
+}
+
+#[test]
+fn convert_test() {
+    let mut exts = vec!["txt"];
+    if cfg!(feature = "json") {
+        exts.extend(["json", "jsonl"]);
+    }
+    if cfg!(feature = "yaml") {
+        exts.push("yaml");
+    }
+
+    let mut c = test_config();
+    c.headers = vec![];
+    c.script = "test/test_script.txt".to_string();
+    let rows = c.to_vector().unwrap();
+
+    for ext in exts {
+        let to = std::env::temp_dir().join(format!(
+            "noop-client-convert-{}.{}",
+            std::process::id(),
+            ext
+        ));
+        let to = to.to_str().unwrap();
+        assert_eq!(c.convert(to).unwrap(), 6);
+
+        // Reads back as the same rows.
+        let mut converted = c.clone();
+        converted.script = to.to_string();
+        let converted = converted.to_vector().unwrap();
+        assert_eq!(converted.len(), rows.len());
+        for (a, b) in rows.iter().zip(&converted) {
+            assert_eq!(
+                (a.iterations, &a.method, &a.endpoint, &a.headers, a.sleep),
+                (b.iterations, &b.method, &b.endpoint, &b.headers, b.sleep)
+            );
+        }
+        let _ = fs::remove_file(to);
+    }
+
+    assert!(c.convert("script.ack").is_err());
+}
+
+#[cfg(test)]
+fn test_script(name: &str, body: &str) -> String {
+    let path = std::env::temp_dir().join(format!("noop-client-{}-{}", std::process::id(), name));
+    fs::write(&path, body).unwrap();
+    return path.to_str().unwrap().to_string();
+}
+
+#[test]
+fn think_time_test() {
+    let mut c = test_config();
+    c.users = 1;
+    c.think_time = random::Distribution::parse("exp:200ms").unwrap();
+    c.script = test_script(
+        "think_time.txt",
+        "iterations|endpoint|think_time\n1|http://localhost:3000/|\n1|http://localhost:3000/|uniform:1s,3s\n",
+    );
+
+    let rows = c.to_vector().unwrap();
+    assert_eq!(rows[0].think_time, c.think_time);
+    assert_eq!(rows[1].think_time.to_string(), "uniform:1000ms,3000ms");
+    assert!(c.validate().problems.is_empty());
+    let _ = fs::remove_file(&c.script);
+
+    c.script = test_script(
+        "think_time_invalid.txt",
+        "iterations|endpoint|think_time\n1|http://localhost:3000/|poisson:1s\n",
+    );
+    assert_eq!(
+        c.to_vector().err().unwrap().to_string(),
+        format!(
+            "{}:2: think_time: invalid distribution: poisson:1s",
+            c.script
+        )
+    );
+    let _ = fs::remove_file(&c.script);
+}
+
+#[test]
+fn weight_test() {
+    let mut c = test_config();
+    c.script = test_script(
+        "weight.txt",
+        "endpoint|weight\nhttp://localhost:3000/reads|70\nhttp://localhost:3000/writes|0\n",
+    );
+
+    let rows = c.to_vector().unwrap();
+    assert_eq!((rows[0].weight, rows[1].weight), (70.0, 1.0));
+    let _ = fs::remove_file(&c.script);
+
+    c.script = test_script(
+        "weight_negative.txt",
+        "endpoint|weight\nhttp://localhost:3000/|-1\n",
+    );
+    assert_eq!(
+        c.to_vector().err().unwrap().to_string(),
+        format!("{}:2: weight: -1 is negative", c.script)
+    );
+    let _ = fs::remove_file(&c.script);
+}
+
+#[test]
+fn located_test() {
+    assert_eq!(
+        located(
+            "script.json",
+            3,
+            7,
+            &"expected `,` or `}` at line 3 column 7"
+        )
+        .to_string(),
+        "script.json:3:7: expected `,` or `}`"
+    );
+
+    let mut c = test_config();
+    c.script = test_script(
+        "located.txt",
+        "iterations|method|sleep\n1|GET|0\n2|POST|ten\n",
+    );
+    let err = c.to_vector().unwrap_err().to_string();
+    assert_eq!(
+        err,
+        format!("{}:3:8: sleep: invalid digit found in string", c.script)
+    );
+    let _ = fs::remove_file(&c.script);
+
+    let mut scripts = vec![];
+    if cfg!(feature = "json") {
+        scripts.push(("located.json", "[\n  {\"iterations\": \"x\"}\n]", ":2:"));
+        scripts.push(("located.jsonl", "{}\n{\"sleep\": -1}\n", ":2:"));
+    }
+    if cfg!(feature = "yaml") {
+        scripts.push(("located.yaml", "requests:\n  - iterations: x\n", ":2:"));
+    }
+    if cfg!(feature = "toml") {
+        scripts.push(("located.toml", "[[request]]\niterations = \"x\"\n", ":2:"));
+    }
+    for (name, body, at) in scripts {
+        c.script = test_script(name, body);
+        let err = c.to_vector().unwrap_err().to_string();
+        assert!(
+            err.starts_with(&format!("{}{}", c.script, at)),
+            "{}: {}",
+            name,
+            err
+        );
+        let _ = fs::remove_file(&c.script);
+    }
+}
+
+#[test]
+#[cfg(feature = "yaml")]
+fn unknown_keys_test() {
+    let mut c = test_config();
+    c.script = test_script(
+        "unknown.yaml",
+        "defaults: { base_url: http://localhost:3000 }\nvar: { run: run1 }\nrequests:\n  - endpoint: /request1\n    slep: 10\n",
+    );
+    assert_eq!(
+        c.validate().problems,
+        vec![
+            format!("{}: unknown key 'var'", c.script),
+            format!("{}:4: unknown key 'slep'", c.script),
+        ]
+    );
+    let _ = fs::remove_file(&c.script);
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn jsonl_unknown_keys_test() {
+    let mut c = test_config();
+    c.script = test_script(
+        "unknown.jsonl",
+        "{\"endpoint\": \"/request1\"}\n\n{\"endpoint\": \"/request2\", \"slep\": 10}\n",
+    );
+    assert_eq!(
+        c.validate().problems,
+        vec![format!("{}:3: unknown key 'slep'", c.script)]
+    );
+    let _ = fs::remove_file(&c.script);
 }
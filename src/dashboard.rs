@@ -0,0 +1,348 @@
+//! Live terminal dashboard for '--tui'.
+//!
+//! Kept by the housekeeping thread alongside 'State'; it's fed the same messages and
+//! redrawn a few times a second. 'q', 'Esc' or 'Ctrl-C' stop dispatching and wait on what's
+//! in flight, pressing again quits straight away.
+
+use crate::errors::ClientError;
+use crate::histogram::Histogram;
+use crate::report::RowStats;
+use crate::state::{Outcome, State};
+
+use std::collections;
+use std::time;
+
+use ratatui::crossterm::event;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Sparkline, Table, Wrap};
+use ratatui::Frame;
+
+pub static REFRESH: time::Duration = time::Duration::from_millis(250);
+
+/// Rows shown in the per row table, busiest first.
+static MAX_ROWS: usize = 10;
+
+/// What's shown, beyond the 'State' summary.
+struct Stats {
+    start: time::Instant,
+    started: usize,
+    processed: usize,
+    /// Responses per elapsed second.
+    timeline: Vec<u64>,
+    latency: Histogram,
+    codes: collections::BTreeMap<u16, usize>,
+    errors: collections::BTreeMap<String, usize>,
+    rows: collections::BTreeMap<usize, RowStats>,
+}
+
+impl Stats {
+    fn new() -> Stats {
+        return Stats {
+            start: time::Instant::now(),
+            started: 0,
+            processed: 0,
+            timeline: vec![],
+            latency: Histogram::new(),
+            codes: collections::BTreeMap::new(),
+            errors: collections::BTreeMap::new(),
+            rows: collections::BTreeMap::new(),
+        };
+    }
+
+    fn record(&mut self, outcome: &Outcome) {
+        self.processed += 1;
+
+        let second = self.start.elapsed().as_secs() as usize;
+        if second >= self.timeline.len() {
+            self.timeline.resize(second + 1, 0);
+        }
+        self.timeline[second] += 1;
+
+        if outcome.error > 0 {
+            *self.errors.entry(outcome.error_kind.clone()).or_default() += 1;
+        } else {
+            *self.codes.entry(outcome.code).or_default() += 1;
+        }
+
+        self.rows.entry(outcome.row).or_default().record(outcome);
+
+        if let Some(timings) = &outcome.timings {
+            self.latency.record(timings.total);
+        }
+    }
+
+    /// Responses in the last full second; the current one is still filling up.
+    fn rps(&self) -> u64 {
+        return match self.timeline.len() {
+            0 | 1 => self.processed as u64,
+            n => self.timeline[n - 2],
+        };
+    }
+
+    fn average_rps(&self) -> f64 {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        if elapsed == 0.0 {
+            return 0.0;
+        }
+        return self.processed as f64 / elapsed;
+    }
+
+    fn in_flight(&self) -> usize {
+        return self.started.saturating_sub(self.processed);
+    }
+}
+
+/// What the user's asked for with the keyboard.
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    None,
+    Stop,
+    Quit,
+}
+
+pub struct Dashboard {
+    terminal: ratatui::DefaultTerminal,
+    stats: Stats,
+    drawn: time::Instant,
+    stopping: bool,
+}
+
+impl Dashboard {
+    /// Takes over the terminal, until dropped.
+    pub fn new() -> Result<Dashboard, ClientError> {
+        let terminal = match ratatui::try_init() {
+            Ok(terminal) => terminal,
+            Err(err) => return Err(ClientError::ConfigError(err.to_string())),
+        };
+
+        return Ok(Dashboard {
+            terminal,
+            stats: Stats::new(),
+            drawn: time::Instant::now() - REFRESH,
+            stopping: false,
+        });
+    }
+
+    pub fn started(&mut self) {
+        self.stats.started += 1;
+    }
+
+    pub fn record(&mut self, outcome: &Outcome) {
+        self.stats.record(outcome);
+    }
+
+    /// Reads pending key presses.
+    pub fn input(&mut self) -> Input {
+        while let Ok(true) = event::poll(time::Duration::ZERO) {
+            let key = match event::read() {
+                Ok(event::Event::Key(key)) if key.kind == event::KeyEventKind::Press => key,
+                _ => continue,
+            };
+
+            let ctrl_c = key.code == event::KeyCode::Char('c')
+                && key.modifiers.contains(event::KeyModifiers::CONTROL);
+            if ctrl_c || key.code == event::KeyCode::Char('q') || key.code == event::KeyCode::Esc {
+                if self.stopping {
+                    return Input::Quit;
+                }
+                self.stopping = true;
+                return Input::Stop;
+            }
+        }
+        return Input::None;
+    }
+
+    /// Redraws, at most every 'REFRESH'.
+    pub fn draw(&mut self, state: &State) -> Result<(), ClientError> {
+        if self.drawn.elapsed() < REFRESH {
+            return Ok(());
+        }
+        self.drawn = time::Instant::now();
+
+        let summary = state.string();
+        let drawn = self
+            .terminal
+            .draw(|frame| render(frame, &self.stats, &summary, self.stopping));
+        return match drawn {
+            Ok(_) => Ok(()),
+            Err(err) => Err(ClientError::StateParseError(err.to_string())),
+        };
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+fn render(frame: &mut Frame, stats: &Stats, summary: &str, stopping: bool) {
+    let [header_area, sparkline_area, middle_area, rows_area] = Layout::vertical([
+        Constraint::Length(6),
+        Constraint::Length(6),
+        Constraint::Length(9),
+        Constraint::Min(4),
+    ])
+    .areas(frame.area());
+    let [latency_area, codes_area] =
+        Layout::horizontal([Constraint::Length(24), Constraint::Min(20)]).areas(middle_area);
+
+    let status = match stopping {
+        true => "stopping, waiting on requests in flight; q to quit now",
+        false => "q / esc: stop",
+    };
+    let header = vec![
+        Line::from(summary.to_string()),
+        Line::from(format!(
+            "rps={} avg_rps={:.1} in_flight={}",
+            stats.rps(),
+            stats.average_rps(),
+            stats.in_flight()
+        )),
+        Line::from(status).dim(),
+    ];
+    frame.render_widget(
+        Paragraph::new(header)
+            .wrap(Wrap { trim: true })
+            .block(Block::bordered().title(" noop-client ")),
+        header_area,
+    );
+
+    // As much of the timeline as fits, latest on the right.
+    let width = sparkline_area.width.saturating_sub(2) as usize;
+    let first = stats.timeline.len().saturating_sub(width);
+    frame.render_widget(
+        Sparkline::default()
+            .block(Block::bordered().title(" responses / second "))
+            .data(&stats.timeline[first..])
+            .style(Style::new().green()),
+        sparkline_area,
+    );
+
+    let ms = |micros: u64| format!("{:.1}ms", micros as f64 / 1000.0);
+    let latency = ["p50", "p90", "p95", "p99"]
+        .iter()
+        .zip([50.0, 90.0, 95.0, 99.0])
+        .map(|(name, p)| Row::new(vec![name.to_string(), ms(stats.latency.percentile(p))]))
+        .chain([
+            Row::new(vec!["max".to_string(), ms(stats.latency.max())]),
+            Row::new(vec!["mean".to_string(), ms(stats.latency.mean())]),
+        ]);
+    frame.render_widget(
+        Table::new(latency, [Constraint::Length(6), Constraint::Min(10)])
+            .block(Block::bordered().title(" latency ")),
+        latency_area,
+    );
+
+    let codes = stats
+        .codes
+        .iter()
+        .map(|(code, count)| Row::new(vec![code.to_string(), count.to_string()]))
+        .chain(
+            stats
+                .errors
+                .iter()
+                .map(|(kind, count)| Row::new(vec![kind.clone(), count.to_string()]).red()),
+        );
+    frame.render_widget(
+        Table::new(codes, [Constraint::Min(10), Constraint::Length(10)])
+            .block(Block::bordered().title(" status codes / errors ")),
+        codes_area,
+    );
+
+    let mut busiest: Vec<(&usize, &RowStats)> = stats.rows.iter().collect();
+    busiest.sort_by_key(|(_, r)| std::cmp::Reverse(r.success + r.fail + r.error));
+    let rows = busiest.iter().take(MAX_ROWS).map(|(n, r)| {
+        Row::new(vec![
+            n.to_string(),
+            r.label.clone(),
+            r.success.to_string(),
+            r.fail.to_string(),
+            r.error.to_string(),
+            ms(r.latency.percentile(50.0)),
+            ms(r.latency.percentile(99.0)),
+        ])
+    });
+    frame.render_widget(
+        Table::new(
+            rows,
+            [
+                Constraint::Length(5),
+                Constraint::Min(20),
+                Constraint::Length(8),
+                Constraint::Length(8),
+                Constraint::Length(8),
+                Constraint::Length(9),
+                Constraint::Length(9),
+            ],
+        )
+        .header(
+            Row::new(vec![
+                "row", "request", "success", "fail", "error", "p50", "p99",
+            ])
+            .bold(),
+        )
+        .block(Block::bordered().title(" rows ")),
+        rows_area,
+    );
+}
+
+#[test]
+fn stats_test() {
+    let mut stats = Stats::new();
+    stats.started = 3;
+    stats.record(&Outcome {
+        success: 1,
+        code: 200,
+        row: 1,
+        label: "GET http://localhost:3000/".to_string(),
+        ..Default::default()
+    });
+    stats.record(&Outcome {
+        error: 1,
+        error_kind: "Connection Failed".to_string(),
+        row: 2,
+        ..Default::default()
+    });
+
+    assert_eq!(stats.in_flight(), 1);
+    assert_eq!(stats.rps(), 2);
+    assert_eq!(stats.codes.get(&200), Some(&1));
+    assert_eq!(stats.errors.get("Connection Failed"), Some(&1));
+    assert_eq!(stats.rows.len(), 2);
+    assert_eq!(
+        stats.rows[&1].label,
+        "GET http://localhost:3000/".to_string()
+    );
+}
+
+#[test]
+fn render_test() {
+    let mut stats = Stats::new();
+    stats.record(&Outcome {
+        fail: 1,
+        code: 404,
+        row: 1,
+        label: "GET http://localhost:3000/404".to_string(),
+        ..Default::default()
+    });
+
+    let backend = ratatui::backend::TestBackend::new(100, 30);
+    let mut terminal = ratatui::Terminal::new(backend).unwrap();
+    terminal
+        .draw(|frame| render(frame, &stats, "requested=1 processed=1", true))
+        .unwrap();
+
+    let screen: String = terminal
+        .backend()
+        .buffer()
+        .content()
+        .iter()
+        .map(|cell| cell.symbol())
+        .collect();
+    assert!(screen.contains("requested=1 processed=1"));
+    assert!(screen.contains("stopping, waiting on requests in flight"));
+    assert!(screen.contains("GET http://localhost:3000/404"));
+}
@@ -0,0 +1,466 @@
+//! Distributed runs, for more load than one machine can make; a coordinator splits its
+//! plan across agents and merges the outcomes they stream back.
+//!
+//! Agents listen on TCP, serving a coordinator at a time. The coordinator sends each agent
+//! the options to run with and its share of every row's iterations, waits on them all being
+//! ready, then sends a time to start at; so they start together, given clocks kept in sync
+//! (e.g. by NTP). Outcomes are passed on to housekeeping as they come, so the state, report
+//! and other outputs are the coordinator's as for a local run. Messages are JSON lines.
+
+use crate::config::Config;
+use crate::errors::ClientError;
+use crate::plan::Plan;
+use crate::random;
+use crate::state::{Message, Outcome};
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net;
+use std::sync;
+use std::thread;
+use std::time;
+
+use std::io::{BufRead, Write};
+
+use serde_derive::{Deserialize, Serialize};
+
+/// Lead time given with the start time, for it to reach every agent.
+const START_DELAY: time::Duration = time::Duration::from_millis(100);
+
+/// Executes a plan, sending outcomes to housekeeping; what an agent runs its share on.
+pub type Engine = fn(&Config, &mut Plan, &sync::mpsc::Sender<Message>) -> Result<(), ClientError>;
+
+/// Coordinator to agent.
+#[derive(Debug, Serialize, Deserialize)]
+enum Request {
+    /// Options to run with, and the agent's share of the rows.
+    Plan {
+        config: Box<Config>,
+        rows: Vec<Config>,
+    },
+    /// When to start, in milliseconds since the epoch.
+    Start { at: u64 },
+}
+
+/// Agent to coordinator.
+#[derive(Debug, Serialize, Deserialize)]
+enum Reply {
+    Ready,
+    Started,
+    Outcome(Box<Outcome>),
+    /// With the number of requests made.
+    Done {
+        requests: usize,
+    },
+    Error(String),
+}
+
+/// Writes a message as a line, leaving flushing to the caller.
+fn write<T: serde::Serialize>(out: &mut impl Write, message: &T) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, message)?;
+    return out.write_all(b"\n");
+}
+
+/// Reads the next message; None once the other end has closed the connection.
+fn read<T: serde::de::DeserializeOwned>(input: &mut impl BufRead) -> io::Result<Option<T>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    return Ok(Some(serde_json::from_str(&line)?));
+}
+
+/// A connection to a coordinator, or agent.
+struct Peer {
+    /// Coordinator or agent, and its address.
+    name: String,
+    input: io::BufReader<net::TcpStream>,
+    out: io::BufWriter<net::TcpStream>,
+}
+
+impl Peer {
+    fn new(name: String, stream: net::TcpStream) -> Result<Peer, ClientError> {
+        let input = match stream.try_clone() {
+            Ok(input) => io::BufReader::new(input),
+            Err(err) => return Err(ClientError::AgentError(format!("{}: {}", name, err))),
+        };
+        return Ok(Peer {
+            name,
+            input,
+            out: io::BufWriter::new(stream),
+        });
+    }
+
+    fn connect(agent: &str) -> Result<Peer, ClientError> {
+        return match net::TcpStream::connect(agent) {
+            Ok(stream) => Peer::new(format!("agent {}", agent), stream),
+            Err(err) => Err(ClientError::ConfigError(format!(
+                "invalid agent {}: {}",
+                agent, err
+            ))),
+        };
+    }
+
+    fn error(&self, err: &dyn fmt::Display) -> ClientError {
+        return ClientError::AgentError(format!("{}: {}", self.name, err));
+    }
+
+    fn send<T: serde::Serialize>(&mut self, message: &T) -> Result<(), ClientError> {
+        return write(&mut self.out, message)
+            .and_then(|_| self.out.flush())
+            .map_err(|err| self.error(&err));
+    }
+
+    fn receive<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, ClientError> {
+        return match read(&mut self.input) {
+            Ok(Some(message)) => Ok(message),
+            Ok(None) => Err(self.error(&"connection closed")),
+            Err(err) => Err(self.error(&err)),
+        };
+    }
+
+    /// Passes on what an agent streams back, until it's done; with how many requests it made.
+    fn collect(mut self, state_tx: &sync::mpsc::Sender<Message>) -> Result<usize, ClientError> {
+        loop {
+            match self.receive()? {
+                Reply::Started => {
+                    let _ = state_tx.send(Message::Started);
+                }
+                Reply::Outcome(outcome) => {
+                    let _ = state_tx.send(Message::Outcome(outcome));
+                }
+                Reply::Done { requests } => return Ok(requests),
+                Reply::Error(err) => return Err(self.error(&err)),
+                Reply::Ready => return Err(self.error(&"unexpected ready")),
+            }
+        }
+    }
+}
+
+/// Agents with their share of a plan, ready to start.
+pub struct Coordinator {
+    agents: Vec<Peer>,
+}
+
+impl Coordinator {
+    /// Sends each agent its share of the plan, then waits on them all being ready.
+    pub fn new(config: &Config, agents: &[String], plan: &mut Plan) -> Result<Self, ClientError> {
+        let mut peers = vec![];
+        for (n, (agent, rows)) in agents.iter().zip(plan.split(agents.len())?).enumerate() {
+            let mut peer = Peer::connect(agent)?;
+            // Each agent's a root of its own, so they don't draw the same as each other.
+            peer.send(&Request::Plan {
+                config: Box::new(Config {
+                    seed: random::derive(config.seed, n as u64),
+                    ..config.clone()
+                }),
+                rows,
+            })?;
+            peers.push(peer);
+        }
+
+        for peer in &mut peers {
+            match peer.receive()? {
+                Reply::Ready => {}
+                Reply::Error(err) => return Err(peer.error(&err)),
+                _ => return Err(peer.error(&"expected ready")),
+            }
+        }
+        return Ok(Coordinator { agents: peers });
+    }
+
+    /// Starts agents together, then passes on their outcomes until they're all done;
+    /// counting the requests they made against the plan.
+    pub fn run(
+        mut self,
+        plan: &mut Plan,
+        state_tx: &sync::mpsc::Sender<Message>,
+    ) -> Result<(), ClientError> {
+        let at = (time::SystemTime::now() + START_DELAY)
+            .duration_since(time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        for peer in &mut self.agents {
+            peer.send(&Request::Start { at })?;
+        }
+
+        let collecting: Vec<_> = self
+            .agents
+            .into_iter()
+            .map(|peer| {
+                let state_tx = state_tx.clone();
+                thread::spawn(move || peer.collect(&state_tx))
+            })
+            .collect();
+
+        let mut result = Ok(());
+        for collecting in collecting {
+            let collected = match collecting.join() {
+                Ok(collected) => collected,
+                Err(_) => Err(ClientError::AgentError(
+                    "collecting from an agent panicked".to_string(),
+                )),
+            };
+            match collected {
+                Ok(requests) => plan.add_planned(requests),
+                Err(err) if result.is_ok() => result = Err(err),
+                Err(_) => {}
+            }
+        }
+
+        // Housekeeping can't wait on outcomes from an agent that's gone.
+        if result.is_err() {
+            let _ = state_tx.send(Message::Kill);
+        }
+        return result;
+    }
+}
+
+/// Serves coordinators, a run at a time, until killed.
+pub fn agent(listen: &str, execute: Engine) -> Result<(), ClientError> {
+    let listener = match net::TcpListener::bind(listen) {
+        Ok(listener) => listener,
+        Err(err) => {
+            return Err(ClientError::ConfigError(format!(
+                "invalid listen address {}: {}",
+                listen, err
+            )))
+        }
+    };
+    eprintln!("agent listening on {}", listen);
+
+    for stream in listener.incoming() {
+        let served = match stream {
+            Ok(stream) => serve(stream, execute),
+            Err(err) => Err(ClientError::AgentError(err.to_string())),
+        };
+        if let Err(err) = served {
+            eprintln!("{}", err);
+        }
+    }
+    return Ok(());
+}
+
+/// Runs a coordinator's share of its plan, streaming back outcomes as they come.
+fn serve(stream: net::TcpStream, execute: Engine) -> Result<(), ClientError> {
+    let coordinator = match stream.peer_addr() {
+        Ok(addr) => format!("coordinator {}", addr),
+        Err(_) => "coordinator".to_string(),
+    };
+    let mut peer = Peer::new(coordinator, stream)?;
+
+    let (config, rows) = match peer.receive()? {
+        Request::Plan { config, rows } => (config, rows),
+        _ => return Err(peer.error(&"expected a plan")),
+    };
+    eprintln!("{} rows={}", peer.name, rows.len());
+
+    let mut plan = match ready(&config, rows) {
+        Ok(plan) => plan,
+        Err(err) => {
+            peer.send(&Reply::Error(err.to_string()))?;
+            return Err(peer.error(&err));
+        }
+    };
+    peer.send(&Reply::Ready)?;
+
+    let at = match peer.receive()? {
+        Request::Start { at } => time::UNIX_EPOCH + time::Duration::from_millis(at),
+        _ => return Err(peer.error(&"expected a start time")),
+    };
+    if let Ok(wait) = at.duration_since(time::SystemTime::now()) {
+        thread::sleep(wait);
+    }
+
+    let stop = plan.stopper();
+    let (state_tx, state_rx) = sync::mpsc::channel();
+    let Peer { name, input, out } = peer;
+    let streaming = thread::spawn(move || {
+        let mut out = out;
+        let streamed = stream_outcomes(&mut out, &state_rx);
+        // No sense making requests no one will hear about.
+        if streamed.is_err() {
+            stop.store(true, sync::atomic::Ordering::Relaxed);
+        }
+        return streamed.map(|_| out);
+    });
+
+    let result = execute(&config, &mut plan, &state_tx);
+    drop(state_tx);
+
+    let mut peer = match streaming.join() {
+        Ok(Ok(out)) => Peer { name, input, out },
+        Ok(Err(err)) => return Err(ClientError::AgentError(format!("{}: {}", name, err))),
+        Err(_) => {
+            return Err(ClientError::AgentError(format!(
+                "{}: streaming outcomes panicked",
+                name
+            )))
+        }
+    };
+    match &result {
+        Ok(()) => peer.send(&Reply::Done {
+            requests: plan.planned(),
+        })?,
+        Err(err) => peer.send(&Reply::Error(err.to_string()))?,
+    }
+    eprintln!("{} done requests={}", peer.name, plan.planned());
+    return result;
+}
+
+/// Plans the agent's share of a run.
+fn ready(config: &Config, rows: Vec<Config>) -> Result<Plan, ClientError> {
+    // Response bodies are saved where they're received.
+    if !config.save_responses.is_empty() {
+        if let Err(err) = fs::create_dir_all(&config.save_responses) {
+            return Err(ClientError::ConfigError(format!(
+                "invalid save responses path {}: {}",
+                config.save_responses, err
+            )));
+        }
+    }
+    return Plan::from_rows(rows);
+}
+
+/// Writes outcomes until every sender's gone; flushing whenever it's caught up.
+fn stream_outcomes(
+    out: &mut impl Write,
+    state_rx: &sync::mpsc::Receiver<Message>,
+) -> io::Result<()> {
+    while let Ok(message) = state_rx.recv() {
+        let mut next = Some(message);
+        while let Some(message) = next {
+            match message {
+                Message::Started => write(out, &Reply::Started)?,
+                Message::Outcome(outcome) => write(out, &Reply::Outcome(outcome))?,
+                _ => {}
+            }
+            next = state_rx.try_recv().ok();
+        }
+        out.flush()?;
+    }
+    return Ok(());
+}
+
+/// Stands in for an engine; a successful outcome per request, without sending any.
+#[cfg(test)]
+fn test_engine(
+    _config: &Config,
+    plan: &mut Plan,
+    state_tx: &sync::mpsc::Sender<Message>,
+) -> Result<(), ClientError> {
+    for request in plan {
+        let request = request?;
+        let _ = state_tx.send(Message::Outcome(Box::new(Outcome {
+            success: 1,
+            code: 200,
+            row: request.row,
+            endpoint: request.endpoint.clone(),
+            ..Default::default()
+        })));
+    }
+    return Ok(());
+}
+
+/// An agent serving a single run, on a free port.
+#[cfg(test)]
+fn test_agent() -> (String, thread::JoinHandle<Result<(), ClientError>>) {
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let agent = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        return serve(stream, test_engine);
+    });
+    return (addr, agent);
+}
+
+#[test]
+fn message_test() {
+    let mut out = vec![];
+    write(&mut out, &Request::Start { at: 1000 }).unwrap();
+    write(&mut out, &Reply::Done { requests: 5 }).unwrap();
+    assert_eq!(
+        String::from_utf8(out.clone()).unwrap(),
+        "{\"Start\":{\"at\":1000}}\n{\"Done\":{\"requests\":5}}\n"
+    );
+
+    let mut input = io::BufReader::new(&out[..]);
+    assert!(matches!(
+        read(&mut input).unwrap(),
+        Some(Request::Start { at: 1000 })
+    ));
+    assert!(matches!(
+        read(&mut input).unwrap(),
+        Some(Reply::Done { requests: 5 })
+    ));
+    assert!(read::<Reply>(&mut input).unwrap().is_none());
+}
+
+#[test]
+fn coordinator_test() {
+    let mut c = crate::config::test_config();
+    c.iterations = 5;
+
+    let (first, first_agent) = test_agent();
+    let (second, second_agent) = test_agent();
+
+    let mut plan = Plan::new(&c).unwrap();
+    let coordinator = Coordinator::new(&c, &[first, second], &mut plan).unwrap();
+
+    let (state_tx, state_rx) = sync::mpsc::channel();
+    coordinator.run(&mut plan, &state_tx).unwrap();
+    assert_eq!(plan.planned(), 5);
+    assert!(first_agent.join().unwrap().is_ok());
+    assert!(second_agent.join().unwrap().is_ok());
+    drop(state_tx);
+
+    let outcomes: Vec<Outcome> = state_rx
+        .iter()
+        .filter_map(|message| match message {
+            Message::Outcome(outcome) => Some(*outcome),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(outcomes.len(), 5);
+    assert!(outcomes
+        .iter()
+        .all(|o| o.row == 1 && o.code == 200 && o.endpoint == c.endpoint));
+}
+
+#[test]
+fn lost_agent_test() {
+    let c = crate::config::test_config();
+
+    // Nothing's listening once the listener's dropped.
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    drop(listener);
+
+    let mut plan = Plan::new(&c).unwrap();
+    let err = Coordinator::new(&c, std::slice::from_ref(&addr), &mut plan)
+        .err()
+        .unwrap();
+    assert!(err
+        .to_string()
+        .starts_with(&format!("invalid agent {}: ", addr)));
+
+    // An agent that hangs up on getting its plan, rather than getting ready.
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let agent = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut plan = String::new();
+        io::BufReader::new(stream).read_line(&mut plan).unwrap();
+    });
+
+    let mut plan = Plan::new(&c).unwrap();
+    let err = Coordinator::new(&c, std::slice::from_ref(&addr), &mut plan)
+        .err()
+        .unwrap();
+    agent.join().unwrap();
+    assert_eq!(
+        err.to_string(),
+        format!("agent {}: connection closed", addr)
+    );
+}
@@ -6,6 +6,26 @@ pub enum ClientError {
     HTTPError(String),
     HeaderError(String),
     StateParseError(String),
+    /// A request that got no response, with its kind (e.g. 'Connection Failed') as well as
+    /// what happened.
+    RequestError(String, String),
+    /// Between a coordinator and its agents; connections, I/O and the protocol.
+    #[cfg_attr(not(feature = "distributed"), allow(dead_code))]
+    AgentError(String),
+}
+
+impl ClientError {
+    /// What went wrong, without the detail; few enough to count errors by.
+    pub fn kind(&self) -> String {
+        return match self {
+            ClientError::ConfigError(_) => "Config Error".to_string(),
+            ClientError::HTTPError(_) => "HTTP Error".to_string(),
+            ClientError::HeaderError(_) => "Bad Header".to_string(),
+            ClientError::StateParseError(_) => "State Parse Error".to_string(),
+            ClientError::RequestError(kind, _) => kind.clone(),
+            ClientError::AgentError(_) => "Agent Error".to_string(),
+        };
+    }
 }
 
 impl fmt::Display for ClientError {
@@ -15,6 +35,8 @@ impl fmt::Display for ClientError {
             ClientError::HTTPError(s) => return write!(f, "{}", s),
             ClientError::HeaderError(s) => return write!(f, "{}", s),
             ClientError::StateParseError(s) => return write!(f, "{}", s),
+            ClientError::RequestError(_, s) => return write!(f, "{}", s),
+            ClientError::AgentError(s) => return write!(f, "{}", s),
         };
     }
 }
@@ -0,0 +1,374 @@
+//! Per request metric exporters for '--statsd' and '--influx'.
+//!
+//! Fed by the housekeeping thread alongside 'State'. StatsD gets a UDP packet per
+//! response, leaving aggregation to the server; InfluxDB line protocol is buffered and
+//! written in batches, to an HTTP write endpoint or appended to a file, from a writer thread
+//! of its own; so a slow endpoint doesn't hold up housekeeping.
+
+use crate::config::Config;
+use crate::errors::ClientError;
+use crate::state::Outcome;
+
+use std::fs;
+use std::io::{self, Write};
+use std::net::{self, ToSocketAddrs};
+use std::sync;
+use std::thread;
+use std::time;
+
+/// Buffered line protocol is written once it's this old, or this long.
+static INFLUX_FLUSH_EVERY: time::Duration = time::Duration::from_secs(1);
+static INFLUX_BATCH: usize = 5000;
+
+pub struct Exporters {
+    statsd: Option<Statsd>,
+    influx: Option<Influx>,
+}
+
+impl Exporters {
+    pub fn new(config: &Config) -> Result<Exporters, ClientError> {
+        let statsd = match config.statsd.is_empty() {
+            true => None,
+            false => Some(Statsd::new(&config.statsd, &config.statsd_prefix)?),
+        };
+        let influx = match config.influx.is_empty() {
+            true => None,
+            false => Some(Influx::new(&config.influx)?),
+        };
+        return Ok(Exporters { statsd, influx });
+    }
+
+    pub fn record(&mut self, outcome: &Outcome) {
+        if let Some(statsd) = &self.statsd {
+            statsd.record(outcome);
+        }
+
+        if let Some(influx) = &mut self.influx {
+            influx.record(outcome);
+            if influx.due() {
+                influx.flush();
+            }
+        }
+    }
+
+    /// Writes out anything still buffered, waiting on it being written; once the run's done.
+    pub fn flush(&mut self) {
+        if let Some(mut influx) = self.influx.take() {
+            influx.flush();
+        }
+    }
+}
+
+struct Statsd {
+    socket: net::UdpSocket,
+    prefix: String,
+}
+
+impl Statsd {
+    /// Binds to an address of the same family as the server's, so IPv6 servers work too.
+    fn new(addr: &str, prefix: &str) -> Result<Statsd, ClientError> {
+        let socket = addr.to_socket_addrs().and_then(|mut addrs| {
+            let target = match addrs.next() {
+                Some(target) => target,
+                None => return Err(io::Error::new(io::ErrorKind::NotFound, "no address")),
+            };
+            let local = match target {
+                net::SocketAddr::V4(_) => "0.0.0.0:0",
+                net::SocketAddr::V6(_) => "[::]:0",
+            };
+            let socket = net::UdpSocket::bind(local)?;
+            socket.connect(target)?;
+            return Ok(socket);
+        });
+        return match socket {
+            Ok(socket) => Ok(Statsd {
+                socket,
+                prefix: prefix.to_string(),
+            }),
+            Err(err) => Err(ClientError::ConfigError(format!(
+                "invalid statsd address {}: {}",
+                addr, err
+            ))),
+        };
+    }
+
+    /// Sent and forgotten; a StatsD server that's not listening shouldn't hold up the run.
+    fn record(&self, outcome: &Outcome) {
+        let _ = self.socket.send(self.packet(outcome).as_bytes());
+    }
+
+    /// Newline separated metrics, as one packet.
+    fn packet(&self, outcome: &Outcome) -> String {
+        let p = &self.prefix;
+        let mut lines = vec![format!("{}.requests:1|c", p)];
+        if outcome.error > 0 {
+            lines.push(format!("{}.errors:1|c", p));
+        } else {
+            lines.push(format!("{}.responses.{}:1|c", p, outcome.code));
+        }
+        if let Some(timings) = &outcome.timings {
+            lines.push(format!(
+                "{}.latency:{}|ms",
+                p,
+                timings.total.as_micros() as f64 / 1000.0
+            ));
+        }
+        lines.push(format!("{}.bytes_received:{}|c", p, outcome.bytes_received));
+        lines.push(format!("{}.bytes_sent:{}|c", p, outcome.bytes_sent));
+        return lines.join("\n");
+    }
+}
+
+struct Influx {
+    lines: Vec<String>,
+    flushed: time::Instant,
+    /// Batches to write.
+    tx: Option<sync::mpsc::Sender<String>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Influx {
+    fn new(target: &str) -> Result<Influx, ClientError> {
+        if !is_url(target) {
+            // Fail up front, rather than on the first flush.
+            if let Err(err) = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(target)
+            {
+                return Err(ClientError::ConfigError(format!(
+                    "invalid influx file {}: {}",
+                    target, err
+                )));
+            }
+        }
+
+        let (tx, rx) = sync::mpsc::channel::<String>();
+        let target = target.to_string();
+        let thread = thread::spawn(move || {
+            for batch in rx {
+                if let Err(err) = write(&target, &batch) {
+                    eprintln!("{}", err);
+                }
+            }
+        });
+
+        return Ok(Influx {
+            lines: vec![],
+            flushed: time::Instant::now(),
+            tx: Some(tx),
+            thread: Some(thread),
+        });
+    }
+
+    fn record(&mut self, outcome: &Outcome) {
+        self.lines.push(line(outcome));
+    }
+
+    fn due(&self) -> bool {
+        return self.lines.len() >= INFLUX_BATCH || self.flushed.elapsed() >= INFLUX_FLUSH_EVERY;
+    }
+
+    /// Hands what's buffered to the writer thread.
+    fn flush(&mut self) {
+        self.flushed = time::Instant::now();
+        if self.lines.is_empty() {
+            return;
+        }
+
+        let mut batch = self.lines.join("\n");
+        batch.push('\n');
+        self.lines.clear();
+
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(batch);
+        }
+    }
+}
+
+impl Drop for Influx {
+    /// Waits on what's been flushed being written out.
+    fn drop(&mut self) {
+        drop(self.tx.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Writes a batch of lines to an HTTP(S) write endpoint, or appends them to a file.
+fn write(target: &str, batch: &str) -> Result<(), ClientError> {
+    if is_url(target) {
+        return match ureq::post(target).send_string(batch) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(ClientError::HTTPError(format!(
+                "influx write to {} failed: {}",
+                target, err
+            ))),
+        };
+    }
+
+    let written = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(target)
+        .and_then(|mut file| file.write_all(batch.as_bytes()));
+    return match written {
+        Ok(_) => Ok(()),
+        Err(err) => Err(ClientError::ConfigError(format!(
+            "influx write to {} failed: {}",
+            target, err
+        ))),
+    };
+}
+
+fn is_url(target: &str) -> bool {
+    return target.starts_with("http://") || target.starts_with("https://");
+}
+
+/// A line protocol point for an outcome; tagged by script row, request and status or error,
+/// as of when the request was sent. Tags without a value are left out, as InfluxDB rejects
+/// them.
+fn line(outcome: &Outcome) -> String {
+    let mut tags = format!("noop_client,row={}", outcome.row);
+    if !outcome.label.is_empty() {
+        tags.push_str(&format!(",request={}", escape(&outcome.label)));
+    }
+    let mut fields = vec![];
+    if outcome.error > 0 {
+        if !outcome.error_kind.is_empty() {
+            tags.push_str(&format!(",error_kind={}", escape(&outcome.error_kind)));
+        }
+        fields.push("error=1i".to_string());
+    } else {
+        tags.push_str(&format!(",status={}", outcome.code));
+        fields.push(format!("success={}i", outcome.success));
+        fields.push(format!("fail={}i", outcome.fail));
+    }
+    if let Some(timings) = &outcome.timings {
+        fields.push(format!(
+            "latency_ms={}",
+            timings.total.as_micros() as f64 / 1000.0
+        ));
+    }
+    fields.push(format!("bytes_received={}i", outcome.bytes_received));
+    fields.push(format!("bytes_sent={}i", outcome.bytes_sent));
+
+    let nanos = outcome.timestamp as u128 * 1_000_000;
+    return format!("{} {} {}", tags, fields.join(","), nanos);
+}
+
+/// Tag value escaping, per the line protocol.
+fn escape(value: &str) -> String {
+    return value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ");
+}
+
+#[test]
+fn statsd_test() {
+    let listener = net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    listener
+        .set_read_timeout(Some(time::Duration::from_secs(5)))
+        .unwrap();
+    let statsd = Statsd::new(&listener.local_addr().unwrap().to_string(), "noop").unwrap();
+
+    statsd.record(&Outcome {
+        success: 1,
+        code: 200,
+        bytes_received: 74,
+        bytes_sent: 40,
+        timings: Some(crate::timings::Timings {
+            total: time::Duration::from_micros(1500),
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    let mut buf = [0; 512];
+    let n = listener.recv(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&buf[..n]),
+        "noop.requests:1|c\nnoop.responses.200:1|c\nnoop.latency:1.5|ms\nnoop.bytes_received:74|c\nnoop.bytes_sent:40|c"
+    );
+
+    statsd.record(&Outcome {
+        error: 1,
+        ..Default::default()
+    });
+    let n = listener.recv(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).starts_with("noop.requests:1|c\nnoop.errors:1|c\n"));
+
+    // IPv6 servers, where the host has IPv6.
+    if let Ok(listener) = net::UdpSocket::bind("[::1]:0") {
+        listener
+            .set_read_timeout(Some(time::Duration::from_secs(5)))
+            .unwrap();
+        let statsd = Statsd::new(&listener.local_addr().unwrap().to_string(), "noop").unwrap();
+        statsd.record(&Outcome::default());
+        let n = listener.recv(&mut buf).unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).starts_with("noop.requests:1|c\n"));
+    }
+
+    assert!(Statsd::new("not an address", "noop").is_err());
+}
+
+#[test]
+fn line_test() {
+    assert_eq!(
+        line(
+            &Outcome {
+                fail: 1,
+                code: 404,
+                row: 2,
+                label: "GET http://localhost:3000/404".to_string(),
+                timestamp: 1000,
+                bytes_received: 74,
+                timings: Some(crate::timings::Timings {
+                    total: time::Duration::from_micros(1500),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+        ),
+        "noop_client,row=2,request=GET\\ http://localhost:3000/404,status=404 success=0i,fail=1i,latency_ms=1.5,bytes_received=74i,bytes_sent=0i 1000000000"
+    );
+    assert_eq!(
+        line(
+            &Outcome {
+                error: 1,
+                row: 1,
+                label: "GET bad".to_string(),
+                error_kind: "Bad URL, or worse".to_string(),
+                timestamp: 1000,
+                ..Default::default()
+            }
+        ),
+        "noop_client,row=1,request=GET\\ bad,error_kind=Bad\\ URL\\,\\ or\\ worse error=1i,bytes_received=0i,bytes_sent=0i 1000000000"
+    );
+    assert_eq!(
+        line(&Outcome {
+            error: 1,
+            ..Default::default()
+        }),
+        "noop_client,row=0 error=1i,bytes_received=0i,bytes_sent=0i 0"
+    );
+}
+
+#[test]
+fn influx_file_test() {
+    let path = std::env::temp_dir().join(format!("noop-client-influx-{}.txt", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = fs::remove_file(path);
+
+    let mut influx = Influx::new(path).unwrap();
+    influx.record(&Outcome::default());
+    influx.record(&Outcome::default());
+    influx.flush();
+    influx.flush();
+    drop(influx);
+
+    assert_eq!(fs::read_to_string(path).unwrap().lines().count(), 2);
+    let _ = fs::remove_file(path);
+}
@@ -0,0 +1,162 @@
+//! Latency histogram.
+//!
+//! Log-linear buckets (in microseconds) with 64 sub-buckets per power of two, keeping
+//! percentiles within ~1.5% while memory stays flat regardless of how many values are
+//! recorded.
+
+use std::time;
+
+const SUB_BUCKETS: u64 = 64;
+const LINEAR: u64 = SUB_BUCKETS * 2;
+
+#[derive(Debug, Default, Clone)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        return Histogram::default();
+    }
+
+    pub fn record(&mut self, duration: time::Duration) {
+        self.record_micros(duration.as_micros() as u64);
+    }
+
+    pub fn record_micros(&mut self, micros: u64) {
+        let i = index(micros);
+        if i >= self.buckets.len() {
+            self.buckets.resize(i + 1, 0);
+        }
+        self.buckets[i] += 1;
+
+        if self.count == 0 || micros < self.min {
+            self.min = micros;
+        }
+        self.max = self.max.max(micros);
+        self.count += 1;
+        self.sum += micros as u128;
+    }
+
+    pub fn count(&self) -> u64 {
+        return self.count;
+    }
+
+    pub fn min(&self) -> u64 {
+        return self.min;
+    }
+
+    pub fn max(&self) -> u64 {
+        return self.max;
+    }
+
+    pub fn mean(&self) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        return (self.sum / self.count as u128) as u64;
+    }
+
+    /// Value (in microseconds) at percentile 'p', where 0 < p <= 100.
+    pub fn percentile(&self, p: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+
+        let rank = ((p / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen: u64 = 0;
+        for (i, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return value(i).clamp(self.min, self.max);
+            }
+        }
+        return self.max;
+    }
+
+    /// Non-empty buckets as (value in microseconds, count), in value order.
+    #[cfg_attr(not(any(feature = "json", feature = "yaml")), allow(dead_code))]
+    pub fn buckets(&self) -> Vec<(u64, u64)> {
+        return self
+            .buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(i, count)| (value(i).clamp(self.min, self.max), *count))
+            .collect();
+    }
+}
+
+fn index(micros: u64) -> usize {
+    if micros < LINEAR {
+        return micros as usize;
+    }
+
+    let shift = (63 - micros.leading_zeros() as u64) - 6;
+    let top = micros >> shift;
+    return (LINEAR + (shift - 1) * SUB_BUCKETS + (top - SUB_BUCKETS)) as usize;
+}
+
+/// Midpoint of the bucket at index 'i'.
+fn value(i: usize) -> u64 {
+    let i = i as u64;
+    if i < LINEAR {
+        return i;
+    }
+
+    let shift = (i - LINEAR) / SUB_BUCKETS + 1;
+    let top = (i - LINEAR) % SUB_BUCKETS + SUB_BUCKETS;
+    return (top << shift) + (1 << shift) / 2;
+}
+
+#[test]
+fn index_test() {
+    assert_eq!(index(0), 0);
+    assert_eq!(index(127), 127);
+    assert_eq!(index(128), 128);
+    assert_eq!(index(129), 128);
+    assert_eq!(index(130), 129);
+    assert_eq!(index(256), 192);
+
+    for micros in [1_000, 12_345, 1_000_000, 60_000_000, u64::MAX / 2] {
+        let v = value(index(micros));
+        let err = (v as f64 - micros as f64).abs() / micros as f64;
+        assert!(err < 0.016, "{} -> {} ({})", micros, v, err);
+    }
+}
+
+#[test]
+fn percentile_test() {
+    let mut h = Histogram::new();
+    assert_eq!(h.percentile(50.0), 0);
+
+    for micros in 1..=100 {
+        h.record_micros(micros);
+    }
+    assert_eq!(h.count(), 100);
+    assert_eq!(h.min(), 1);
+    assert_eq!(h.max(), 100);
+    assert_eq!(h.mean(), 50);
+    assert_eq!(h.percentile(50.0), 50);
+    assert_eq!(h.percentile(99.0), 99);
+    assert_eq!(h.percentile(100.0), 100);
+
+    h.record(time::Duration::from_secs(1));
+    let p = h.percentile(100.0);
+    assert_eq!(p, 1_000_000);
+}
+
+#[test]
+fn buckets_test() {
+    let mut h = Histogram::new();
+    assert!(h.buckets().is_empty());
+
+    for micros in [5, 5, 90, 1_000_000] {
+        h.record_micros(micros);
+    }
+    assert_eq!(h.buckets(), vec![(5, 2), (90, 1), (1_000_000, 1)]);
+}
@@ -1,8 +1,29 @@
+#[cfg(feature = "async")]
+mod async_engine;
 mod client;
+#[cfg(feature = "json")]
+mod compare;
+#[cfg(feature = "compression")]
+mod compression;
 mod config;
+#[cfg(feature = "tui")]
+mod dashboard;
+#[cfg(feature = "distributed")]
+mod distributed;
 mod errors;
+mod exporters;
+mod histogram;
+#[cfg(feature = "prometheus")]
+mod metrics;
+mod output;
+mod plan;
+mod random;
+mod replay;
+mod report;
+mod results;
 mod state;
 mod threadpool;
+mod timings;
 
 use crate::errors::ClientError;
 
@@ -12,116 +33,456 @@ use std::time;
 
 fn main() -> Result<(), ClientError> {
     // Set up configuration
-    let config = config::Config::new()?;
+    return match config::Command::new()? {
+        config::Command::Run(config) => run(*config, &[]),
+        config::Command::Validate(config) => validate(&config, true),
+        config::Command::Convert(convert) => {
+            let rows = convert.config.convert(&convert.to)?;
+            println!("converted {} rows to {}", rows, convert.to);
+            Ok(())
+        }
+        config::Command::Report(report) => {
+            summarize(&report.results, &report.output, &report.report)
+        }
+        config::Command::Compare(compare) => compare_runs(&compare),
+        config::Command::Agent(agent) => serve(&agent),
+        config::Command::Coordinator(coordinator) => run(*coordinator.config, &coordinator.agents),
+    };
+}
 
+/// Makes requests; split across agents, when there are any.
+fn run(config: config::Config, agents: &[String]) -> Result<(), ClientError> {
     if config.debug {
         println!("DEBUG:: {:?}", config);
     }
 
-    let requests = config.to_vector()?;
-    let requested: usize = requests.clone().into_iter().map(|c| c.iterations).sum();
+    // Problems only; stdout's left to the run's output.
+    if config.check {
+        validate(&config, false)?;
+    }
+
+    if config.dry_run {
+        let dry_run = plan::DryRun::new(&config)?;
+        match config.output.as_str() {
+            #[cfg(feature = "json")]
+            "json" => println!("{}", dry_run.to_json()),
+            _ => println!("{}", dry_run.string()),
+        }
+        return Ok(());
+    }
+
+    // Re-summarize a past run's results, in place of making requests.
+    if !config.summarize.is_empty() {
+        return summarize(&config.summarize, &config.output, &config.report);
+    }
+
+    let formatter = formatter(&config.output)?;
+
+    let mut plan = plan::Plan::new(&config)?;
+
+    // Agents get their share ahead of housekeeping starting the clock.
+    #[cfg(feature = "distributed")]
+    let coordinator = match agents.is_empty() {
+        true => None,
+        false => Some(distributed::Coordinator::new(&config, agents, &mut plan)?),
+    };
+    #[cfg(not(feature = "distributed"))]
+    if !agents.is_empty() {
+        return Err(ClientError::ConfigError(format!(
+            "running on agents {} needs the distributed feature",
+            agents.join(",")
+        )));
+    }
 
     // Housekeeping pool for state and signals.
     let housekeeping = threadpool::ThreadPool::new(1);
 
-    // Set up workers pool for executing requests.
-    let workers = threadpool::ThreadPool::new(config.pool_size);
-
     // Set up state
     let (state_tx, state_rx) = sync::mpsc::channel();
 
-    //let mut state = state::State::new(requested, config.output.clone());
-    let mut state = state::State::new(requested);
+    #[cfg(feature = "tui")]
+    let mut dashboard = match config.tui {
+        true => Some(dashboard::Dashboard::new()?),
+        false => None,
+    };
+    #[cfg(feature = "tui")]
+    let stop = plan.stopper();
+
+    #[cfg(feature = "prometheus")]
+    let metrics = sync::Arc::new(sync::Mutex::new(metrics::Metrics::new()));
+    #[cfg(feature = "prometheus")]
+    if !config.metrics_addr.is_empty() {
+        metrics::serve(&config.metrics_addr, metrics.clone())?;
+    }
+    #[cfg(feature = "prometheus")]
+    let metrics_push = config.metrics_push.clone();
+
+    let mut exporters = exporters::Exporters::new(&config)?;
+    let results = match config.results_file.is_empty() {
+        true => None,
+        false => Some(results::Writer::new(&config.results_file)?),
+    };
+    let mut report = report::Report::new(now_millis(), Some(config.seed));
+    let report_file = config.report.clone();
+
+    let mut state = state::State::streaming();
     let output = config.output.clone();
     housekeeping.execute(move || {
+        let interval = config.report_interval;
+        let mut next_report = time::Instant::now() + interval;
+        let mut reports: usize = 0;
+
         while !state.done() {
-            let (s, f, e, code, kill) = state_rx.recv().unwrap();
-            if kill {
-                state.kill();
+            // Wake for whichever's due first; the next report or a dashboard redraw.
+            #[allow(unused_mut)]
+            let mut wait: Option<time::Duration> = None;
+            if !interval.is_zero() {
+                if time::Instant::now() >= next_report {
+                    print(formatter.interval(&state.interval(), reports == 0));
+                    reports += 1;
+                    next_report += interval;
+                }
+                wait = Some(next_report.saturating_duration_since(time::Instant::now()));
             }
 
-            state.increment(s, f, e);
+            #[cfg(feature = "tui")]
+            if let Some(dashboard) = &mut dashboard {
+                match dashboard.input() {
+                    dashboard::Input::Stop => stop.store(true, sync::atomic::Ordering::Relaxed),
+                    dashboard::Input::Quit => {
+                        stop.store(true, sync::atomic::Ordering::Relaxed);
+                        state.kill();
+                        continue;
+                    }
+                    dashboard::Input::None => {}
+                }
 
-            if config.verbose {
-                println!("code={} {}", code, state.string())
+                if let Err(err) = dashboard.draw(&state) {
+                    eprintln!("{}", err);
+                }
+                wait = Some(wait.map_or(dashboard::REFRESH, |w| w.min(dashboard::REFRESH)));
+            }
+
+            let message = match wait {
+                Some(wait) => match state_rx.recv_timeout(wait) {
+                    Ok(message) => message,
+                    Err(sync::mpsc::RecvTimeoutError::Timeout) => continue,
+                    Err(sync::mpsc::RecvTimeoutError::Disconnected) => break,
+                },
+                None => state_rx.recv().unwrap(),
+            };
+
+            match message {
+                state::Message::Started => {
+                    #[cfg(feature = "tui")]
+                    if let Some(dashboard) = &mut dashboard {
+                        dashboard.started();
+                    }
+
+                    #[cfg(feature = "prometheus")]
+                    if let Ok(mut metrics) = metrics.lock() {
+                        metrics.started();
+                    }
+                }
+                state::Message::Outcome(outcome) => {
+                    state.record(&outcome);
+
+                    #[cfg(feature = "tui")]
+                    if let Some(dashboard) = &mut dashboard {
+                        dashboard.record(&outcome);
+                    }
+
+                    #[cfg(feature = "prometheus")]
+                    if let Ok(mut metrics) = metrics.lock() {
+                        metrics.record(&outcome);
+                    }
+
+                    exporters.record(&outcome);
+                    if let Some(results) = &results {
+                        results.write(&outcome);
+                    }
+                    report.record(&outcome);
+
+                    if config.verbose {
+                        match &outcome.timings {
+                            Some(timings) => println!(
+                                "code={} {} {}",
+                                outcome.code,
+                                timings.string(),
+                                state.string()
+                            ),
+                            None => println!("code={} {}", outcome.code, state.string()),
+                        }
+                    }
+                }
+                state::Message::Planned(requested) => state.plan(requested),
+                state::Message::Kill => state.kill(),
             }
         }
 
-        if !config.verbose && output == "default" {
-            // Give time to finish writing other output
-            thread::sleep(time::Duration::from_millis(250));
-            println!("{}", state.string());
-        } else if output == "json" {
-            // This will be unreachable without json feature because "json" will not be a valid output
+        // Hand the terminal back before reporting.
+        #[cfg(feature = "tui")]
+        drop(dashboard);
 
-            #[cfg(feature = "json")]
-            println!("{}", state.to_json());
-
-            #[cfg(not(feature = "json"))]
-            panic!("It should not be possible for output to equal json");
-        } else if output == "csv" {
-            match state.to_csv() {
-                Ok(csv) => println!("{}", csv),
-                Err(err) => {
-                    eprintln!("{}", ClientError::StateParseError(err.to_string()));
+        exporters.flush();
+
+        // Short runs may well finish between scrapes.
+        #[cfg(feature = "prometheus")]
+        if !metrics_push.is_empty() {
+            if let Ok(metrics) = metrics.lock() {
+                if let Err(err) = metrics::push(&metrics_push, &metrics) {
+                    eprintln!("{}", err);
                 }
             }
         }
+
+        // Everything's been written once the writer's dropped.
+        drop(results);
+
+        if !report_file.is_empty() {
+            if let Err(err) = report.write(&state, &report_file) {
+                eprintln!("{}", err);
+            }
+        }
+
+        // Verbose output's already had the default summary on every line.
+        if output == "default" {
+            if config.verbose {
+                return;
+            }
+            // Give time to finish writing other output
+            thread::sleep(time::Duration::from_millis(250));
+        }
+        print(formatter.summary(&state, &report));
     });
 
-    // Execute requests
-    for request in requests {
-        for _ in 0..request.iterations {
-            let request = request.clone();
-            let state_tx = state_tx.clone();
+    // Execute requests; on the agents, when coordinating.
+    #[cfg(feature = "distributed")]
+    let result = match coordinator {
+        Some(coordinator) => coordinator.run(&mut plan, &state_tx),
+        None => execute(&config, &mut plan, &state_tx),
+    };
+    #[cfg(not(feature = "distributed"))]
+    let result = execute(&config, &mut plan, &state_tx);
 
-            let config = config.clone();
-            workers.execute(move || {
-                request.sleep();
-
-                // Keep track
-                let mut state: (usize, usize, usize, u16, bool) = (0, 0, 0, 0, false);
-
-                // Set up client
-                let client = client::Client::new(request);
-                if client.is_err() {
-                    state.2 = 1;
-                    let _ = state_tx.send(state);
-                    if config.errors {
-                        eprintln!(
-                            "method={} endpoint=\"{}\" error=\"{}\"",
-                            &config.method,
-                            &config.endpoint,
-                            client.unwrap_err(),
-                        )
-                    }
-                    return;
-                }
+    // Let housekeeping know when it's seen everything, then wait for it to report.
+    let _ = state_tx.send(state::Message::Planned(plan.planned()));
+    drop(housekeeping);
 
-                let client = client.unwrap();
-                match client.execute() {
-                    Ok(status) => {
-                        state.3 = status;
-                        if status >= 200 || status < 300 {
-                            state.0 = 1;
-                        } else {
-                            state.1 = 1;
-                        }
-                    }
-                    Err(err) => {
-                        state.2 = 1;
-                        if config.errors {
-                            eprintln!(
-                                "method={} endpoint=\"{}\" error=\"{}\"",
-                                &client.method, &client.endpoint, err,
-                            )
+    return result;
+}
+
+/// Loads and lints the script, or request, printing any problems; and with 'counts', how many
+/// rows, requests and problems there are.
+fn validate(config: &config::Config, counts: bool) -> Result<(), ClientError> {
+    let validation = config.validate();
+    for problem in &validation.problems {
+        eprintln!("{}", problem);
+    }
+    if counts {
+        println!(
+            "rows={} requests={} problems={}",
+            validation.rows,
+            validation.requests,
+            validation.problems.len()
+        );
+    }
+
+    if !validation.problems.is_empty() {
+        return Err(ClientError::ConfigError(
+            "Validation failed, see problems above.".to_string(),
+        ));
+    }
+    return Ok(());
+}
+
+/// Summarizes a past run's results file; with an html report, if 'report' is set.
+fn summarize(results: &str, output: &str, report: &str) -> Result<(), ClientError> {
+    let formatter = formatter(output)?;
+    let records = results::read(results)?;
+    let state = results::summarize(&records);
+    let html_report = results::report(&records);
+    if !report.is_empty() {
+        html_report.write(&state, report)?;
+    }
+    print(formatter.summary(&state, &html_report));
+    return Ok(());
+}
+
+/// Compares a run against a baseline, exiting non-zero on regressions.
+fn compare_runs(config: &config::CompareConfig) -> Result<(), ClientError> {
+    #[cfg(feature = "json")]
+    if compare::compare(config)? > 0 {
+        std::process::exit(1);
+    }
+    #[cfg(not(feature = "json"))]
+    return Err(ClientError::ConfigError(format!(
+        "comparing {} and {} needs the json feature",
+        config.baseline, config.current
+    )));
+    #[allow(unreachable_code)]
+    return Ok(());
+}
+
+/// Makes requests for coordinators, until killed.
+fn serve(agent: &config::AgentConfig) -> Result<(), ClientError> {
+    #[cfg(feature = "distributed")]
+    return distributed::agent(&agent.listen, execute);
+    #[cfg(not(feature = "distributed"))]
+    return Err(ClientError::ConfigError(format!(
+        "serving as an agent on {} needs the distributed feature",
+        agent.listen
+    )));
+}
+
+fn formatter(output: &str) -> Result<Box<dyn output::OutputFormatter>, ClientError> {
+    return match output::formatter(output) {
+        Some(formatter) => Ok(formatter),
+        None => Err(ClientError::ConfigError(format!(
+            "invalid output {}",
+            output
+        ))),
+    };
+}
+
+/// Milliseconds since the epoch.
+fn now_millis() -> u64 {
+    return time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+}
+
+/// Prints formatted output, or why it couldn't be formatted.
+fn print(output: Result<String, ClientError>) {
+    match output {
+        Ok(output) => println!("{}", output),
+        Err(err) => eprintln!("{}", err),
+    }
+}
+
+/// Executes the plan on the configured engine, or as virtual users.
+fn execute(
+    config: &config::Config,
+    plan: &mut plan::Plan,
+    state_tx: &sync::mpsc::Sender<state::Message>,
+) -> Result<(), ClientError> {
+    return match config.engine.as_str() {
+        _ if config.users > 0 => run_users(config, plan, state_tx),
+        #[cfg(feature = "async")]
+        "async" => async_engine::run(config, plan, state_tx),
+        _ => run_threads(config, plan, state_tx),
+    };
+}
+
+/// Executes the plan on a pool of OS threads, each blocking on a request at a time.
+fn run_threads(
+    config: &config::Config,
+    plan: &mut plan::Plan,
+    state_tx: &sync::mpsc::Sender<state::Message>,
+) -> Result<(), ClientError> {
+    // Set up workers pool for executing requests.
+    let workers = threadpool::ThreadPool::new(config.pool_size);
+
+    // Sleeps are drawn from the workers' streams as requests are dispatched, in plan order;
+    // so a seed gives the same ones whichever thread makes them.
+    let mut rngs = random::Workers::new(config.seed, config.pool_size);
+
+    let start = time::Instant::now();
+    for request in plan {
+        let request = request?;
+
+        request.wait(start);
+
+        let sleep = request.sleep_time().sample(rngs.next());
+        let state_tx = state_tx.clone();
+        let errors = config.errors;
+        let in_flight = config.tracks_in_flight();
+        workers.execute(move || {
+            if !sleep.is_zero() {
+                thread::sleep(sleep);
+            }
+            make_request(&request, errors, in_flight, &state_tx);
+        });
+    }
+
+    return Ok(());
+}
+
+/// Executes the plan as virtual users, each on a thread of its own looping through the
+/// script's rows in order; thinking before each request.
+fn run_users(
+    config: &config::Config,
+    plan: &mut plan::Plan,
+    state_tx: &sync::mpsc::Sender<state::Message>,
+) -> Result<(), ClientError> {
+    let script = sync::Arc::new(plan.script()?);
+    let stop = plan.stopper();
+    let made = sync::Arc::new(sync::atomic::AtomicUsize::new(0));
+    let seed = config.seed;
+
+    let users: Vec<_> = (0..config.users)
+        .map(|user| {
+            let script = script.clone();
+            let stop = stop.clone();
+            let made = made.clone();
+            let state_tx = state_tx.clone();
+            let loops = config.loops;
+            let errors = config.errors;
+            let in_flight = config.tracks_in_flight();
+            thread::spawn(move || {
+                threadpool::set_worker(user);
+                let mut rng = random::Rng::new(random::derive(seed, user as u64));
+                for _ in 0..loops {
+                    for request in script.iter() {
+                        for _ in 0..request.iterations {
+                            if stop.load(sync::atomic::Ordering::Relaxed) {
+                                return;
+                            }
+                            thread::sleep(
+                                request.think_time.sample(&mut rng)
+                                    + request.sleep_time().sample(&mut rng),
+                            );
+
+                            made.fetch_add(1, sync::atomic::Ordering::Relaxed);
+                            make_request(request, errors, in_flight, &state_tx);
                         }
                     }
                 }
+            })
+        })
+        .collect();
 
-                let _ = state_tx.send(state);
-            });
+    for user in users {
+        let _ = user.join();
+    }
+    plan.add_planned(made.load(sync::atomic::Ordering::Relaxed));
+
+    return Ok(());
+}
+
+/// Makes a request, sending its outcome to housekeeping.
+fn make_request(
+    request: &config::Config,
+    errors: bool,
+    in_flight: bool,
+    state_tx: &sync::mpsc::Sender<state::Message>,
+) {
+    if in_flight {
+        let _ = state_tx.send(state::Message::Started);
+    }
+
+    let result = client::Client::new(request).and_then(|client| client.execute());
+    if let Err(err) = &result {
+        if errors {
+            eprintln!(
+                "method={} endpoint=\"{}\" error=\"{}\"",
+                &request.method, &request.endpoint, err,
+            )
         }
     }
-    Ok(())
+
+    let _ = state_tx.send(state::Message::Outcome(Box::new(state::Outcome::new(
+        request, &result,
+    ))));
 }
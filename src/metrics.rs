@@ -0,0 +1,337 @@
+//! Prometheus metrics for '--metrics-addr' and '--metrics-push'.
+//!
+//! Kept by the housekeeping thread alongside 'State' and rendered in the Prometheus text
+//! exposition format; served on '/metrics' from a thread of its own while running, and/or
+//! pushed to a push gateway once the run's done.
+
+use crate::errors::ClientError;
+use crate::state::Outcome;
+
+use std::collections;
+use std::fmt::Write;
+use std::io::{self, BufRead};
+use std::net;
+use std::sync;
+use std::thread;
+use std::time;
+
+/// How long a scrape has to send its request, before the connection's dropped.
+const READ_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+/// Latency histogram bucket upper bounds, in seconds.
+static BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+static CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    started: u64,
+    processed: u64,
+    bytes_received: u64,
+    bytes_sent: u64,
+    /// Request labels by script row.
+    labels: collections::BTreeMap<usize, String>,
+    /// Responses by row and status code.
+    responses: collections::BTreeMap<(usize, u16), u64>,
+    /// Errors by row and kind.
+    errors: collections::BTreeMap<(usize, String), u64>,
+    /// Cumulative counts per 'BUCKETS', then +Inf.
+    buckets: Vec<u64>,
+    latency_sum: f64,
+    latency_count: u64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        return Metrics {
+            buckets: vec![0; BUCKETS.len() + 1],
+            ..Default::default()
+        };
+    }
+
+    pub fn started(&mut self) {
+        self.started += 1;
+    }
+
+    pub fn record(&mut self, outcome: &Outcome) {
+        self.processed += 1;
+        self.bytes_received += outcome.bytes_received;
+        self.bytes_sent += outcome.bytes_sent;
+
+        self.labels
+            .entry(outcome.row)
+            .or_insert_with(|| outcome.label.clone());
+
+        if outcome.error > 0 {
+            *self
+                .errors
+                .entry((outcome.row, outcome.error_kind.clone()))
+                .or_default() += 1;
+        } else {
+            *self
+                .responses
+                .entry((outcome.row, outcome.code))
+                .or_default() += 1;
+        }
+
+        if let Some(timings) = &outcome.timings {
+            let secs = timings.total.as_secs_f64();
+            for (i, le) in BUCKETS.iter().enumerate() {
+                if secs <= *le {
+                    self.buckets[i] += 1;
+                }
+            }
+            self.buckets[BUCKETS.len()] += 1;
+            self.latency_sum += secs;
+            self.latency_count += 1;
+        }
+    }
+
+    /// Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let label = |row: &usize| escape(self.labels.get(row).map_or("", String::as_str));
+
+        let _ = writeln!(
+            out,
+            "# HELP noop_client_responses_total Responses received, by script row and status code."
+        );
+        let _ = writeln!(out, "# TYPE noop_client_responses_total counter");
+        for ((row, status), count) in &self.responses {
+            let _ = writeln!(
+                out,
+                "noop_client_responses_total{{row=\"{}\",request=\"{}\",status=\"{}\"}} {}",
+                row,
+                label(row),
+                status,
+                count
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP noop_client_errors_total Requests without a response, by script row and error kind."
+        );
+        let _ = writeln!(out, "# TYPE noop_client_errors_total counter");
+        for ((row, kind), count) in &self.errors {
+            let _ = writeln!(
+                out,
+                "noop_client_errors_total{{row=\"{}\",request=\"{}\",kind=\"{}\"}} {}",
+                row,
+                label(row),
+                escape(kind),
+                count
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP noop_client_request_duration_seconds Request latency, from sending to the end of the response body."
+        );
+        let _ = writeln!(out, "# TYPE noop_client_request_duration_seconds histogram");
+        for (le, count) in BUCKETS.iter().zip(&self.buckets) {
+            let _ = writeln!(
+                out,
+                "noop_client_request_duration_seconds_bucket{{le=\"{}\"}} {}",
+                le, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "noop_client_request_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            self.buckets[BUCKETS.len()]
+        );
+        let _ = writeln!(
+            out,
+            "noop_client_request_duration_seconds_sum {}",
+            self.latency_sum
+        );
+        let _ = writeln!(
+            out,
+            "noop_client_request_duration_seconds_count {}",
+            self.latency_count
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP noop_client_in_flight Requests started and not yet processed."
+        );
+        let _ = writeln!(out, "# TYPE noop_client_in_flight gauge");
+        let _ = writeln!(
+            out,
+            "noop_client_in_flight {}",
+            self.started.saturating_sub(self.processed)
+        );
+
+        for (name, help, value) in [
+            (
+                "noop_client_bytes_received_total",
+                "Response bytes received.",
+                self.bytes_received,
+            ),
+            (
+                "noop_client_bytes_sent_total",
+                "Request bytes sent.",
+                self.bytes_sent,
+            ),
+        ] {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+
+        return out;
+    }
+}
+
+/// Label value escaping, per the text exposition format.
+fn escape(value: &str) -> String {
+    return value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+}
+
+/// Serves '/metrics' on 'addr' from a background thread, for as long as the run lasts; each
+/// connection on a thread of its own, so a slow one doesn't hold up the next scrape.
+pub fn serve(addr: &str, metrics: sync::Arc<sync::Mutex<Metrics>>) -> Result<(), ClientError> {
+    let listener = match net::TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(err) => {
+            return Err(ClientError::ConfigError(format!(
+                "invalid metrics address {}: {}",
+                addr, err
+            )))
+        }
+    };
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let metrics = metrics.clone();
+            thread::spawn(move || {
+                let _ = respond(stream, &metrics);
+            });
+        }
+    });
+    return Ok(());
+}
+
+fn respond(stream: net::TcpStream, metrics: &sync::Mutex<Metrics>) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = io::BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Skip the headers; there's no body worth reading on a scrape.
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    let (status, body) = match path {
+        "/metrics" => match metrics.lock() {
+            Ok(metrics) => ("200 OK", metrics.render()),
+            Err(_) => ("500 Internal Server Error", String::new()),
+        },
+        _ => ("404 Not Found", String::new()),
+    };
+
+    let mut stream = stream;
+    return io::Write::write_all(
+        &mut stream,
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            CONTENT_TYPE,
+            body.len(),
+            body
+        )
+        .as_bytes(),
+    );
+}
+
+/// Pushes to a push gateway, e.g. 'http://localhost:9091/metrics/job/noop-client'; replacing
+/// what was last pushed for the job.
+pub fn push(url: &str, metrics: &Metrics) -> Result<(), ClientError> {
+    let pushed = ureq::put(url)
+        .set("Content-Type", CONTENT_TYPE)
+        .send_string(&metrics.render());
+    return match pushed {
+        Ok(_) => Ok(()),
+        Err(err) => Err(ClientError::HTTPError(format!(
+            "metrics push to {} failed: {}",
+            url, err
+        ))),
+    };
+}
+
+#[test]
+fn render_test() {
+    let mut metrics = Metrics::new();
+    metrics.started();
+    metrics.started();
+    metrics.record(&Outcome {
+        success: 1,
+        code: 200,
+        row: 1,
+        label: "GET http://localhost:3000/\"a\"".to_string(),
+        bytes_received: 100,
+        timings: Some(crate::timings::Timings {
+            total: std::time::Duration::from_millis(20),
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+
+    let text = metrics.render();
+    assert!(text.contains(
+        "noop_client_responses_total{row=\"1\",request=\"GET http://localhost:3000/\\\"a\\\"\",status=\"200\"} 1\n"
+    ));
+    assert!(text.contains("noop_client_request_duration_seconds_bucket{le=\"0.01\"} 0\n"));
+    assert!(text.contains("noop_client_request_duration_seconds_bucket{le=\"0.025\"} 1\n"));
+    assert!(text.contains("noop_client_request_duration_seconds_bucket{le=\"+Inf\"} 1\n"));
+    assert!(text.contains("noop_client_request_duration_seconds_count 1\n"));
+    assert!(text.contains("noop_client_in_flight 1\n"));
+    assert!(text.contains("noop_client_bytes_received_total 100\n"));
+
+    metrics.record(&Outcome {
+        error: 1,
+        row: 2,
+        label: "GET bad_endpoint".to_string(),
+        error_kind: "Bad URL".to_string(),
+        ..Default::default()
+    });
+    assert!(metrics.render().contains(
+        "noop_client_errors_total{row=\"2\",request=\"GET bad_endpoint\",kind=\"Bad URL\"} 1\n"
+    ));
+}
+
+#[test]
+fn serve_test() {
+    use std::io::{Read, Write};
+
+    let metrics = sync::Arc::new(sync::Mutex::new(Metrics::new()));
+    assert!(serve("not an address", metrics.clone()).is_err());
+
+    // Find a free port first, so the address is known.
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+    serve(&addr.to_string(), metrics).unwrap();
+
+    // A connection that sends nothing doesn't hold up the scrape after it.
+    let _idle = net::TcpStream::connect(addr).unwrap();
+
+    let mut stream = net::TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("noop_client_in_flight 0\n"));
+}
@@ -0,0 +1,406 @@
+//! Output formats for '--output'.
+//!
+//! Each format renders the end of run summary, from 'State' and the per row 'Report', and
+//! interval reports while running. Adding one is a matter of implementing 'OutputFormatter'
+//! and listing it in 'formatter'.
+
+use crate::errors::ClientError;
+use crate::report::{self, Report};
+use crate::state::{IntervalSerialize, State};
+use crate::timings;
+
+pub trait OutputFormatter: Send {
+    /// End of run summary.
+    fn summary(&self, state: &State, report: &Report) -> Result<String, ClientError>;
+
+    /// Report on an interval while running; 'first' for what goes ahead of them all, e.g. a
+    /// header.
+    fn interval(&self, interval: &IntervalSerialize, first: bool) -> Result<String, ClientError>;
+}
+
+/// Formatter for an '--output' value, if it's one that's supported.
+pub fn formatter(output: &str) -> Option<Box<dyn OutputFormatter>> {
+    return match output {
+        "default" => Some(Box::new(DefaultFormatter)),
+        "csv" => Some(Box::new(CsvFormatter)),
+        "markdown" => Some(Box::new(MarkdownFormatter)),
+        "junit" => Some(Box::new(JunitFormatter)),
+        #[cfg(feature = "json")]
+        "json" => Some(Box::new(JsonFormatter)),
+        #[cfg(feature = "yaml")]
+        "yaml" => Some(Box::new(YamlFormatter)),
+        _ => None,
+    };
+}
+
+/// Supported '--output' values.
+pub fn names() -> Vec<&'static str> {
+    return ["default", "csv", "markdown", "junit", "json", "yaml"]
+        .into_iter()
+        .filter(|name| formatter(name).is_some())
+        .collect();
+}
+
+fn parse_error(err: Box<dyn std::error::Error>) -> ClientError {
+    return ClientError::StateParseError(err.to_string());
+}
+
+/// Key=value pairs on a line.
+struct DefaultFormatter;
+
+impl OutputFormatter for DefaultFormatter {
+    fn summary(&self, state: &State, report: &Report) -> Result<String, ClientError> {
+        return Ok(match report.seed() {
+            Some(seed) => format!("{} seed={}", state.string(), seed),
+            None => state.string(),
+        });
+    }
+
+    fn interval(&self, interval: &IntervalSerialize, _: bool) -> Result<String, ClientError> {
+        return Ok(interval.string());
+    }
+}
+
+/// A header and a row; intervals as rows, with the header ahead of the first.
+struct CsvFormatter;
+
+impl OutputFormatter for CsvFormatter {
+    fn summary(&self, state: &State, report: &Report) -> Result<String, ClientError> {
+        return state.to_csv(report).map_err(parse_error);
+    }
+
+    fn interval(&self, interval: &IntervalSerialize, first: bool) -> Result<String, ClientError> {
+        return interval.to_csv(first).map_err(parse_error);
+    }
+}
+
+#[cfg(feature = "json")]
+struct JsonFormatter;
+
+#[cfg(feature = "json")]
+impl OutputFormatter for JsonFormatter {
+    fn summary(&self, state: &State, report: &Report) -> Result<String, ClientError> {
+        return Ok(state.to_json(report));
+    }
+
+    fn interval(&self, interval: &IntervalSerialize, _: bool) -> Result<String, ClientError> {
+        return Ok(interval.to_json());
+    }
+}
+
+/// A document each; so intervals and the summary read as one stream.
+#[cfg(feature = "yaml")]
+struct YamlFormatter;
+
+#[cfg(feature = "yaml")]
+impl OutputFormatter for YamlFormatter {
+    fn summary(&self, state: &State, report: &Report) -> Result<String, ClientError> {
+        return Ok(format!(
+            "---\n{}",
+            state.to_yaml(report).map_err(parse_error)?
+        ));
+    }
+
+    fn interval(&self, interval: &IntervalSerialize, _: bool) -> Result<String, ClientError> {
+        return Ok(format!("---\n{}", interval.to_yaml().map_err(parse_error)?));
+    }
+}
+
+/// Tables, for pasting into issues and pull requests.
+struct MarkdownFormatter;
+
+impl OutputFormatter for MarkdownFormatter {
+    fn summary(&self, state: &State, report: &Report) -> Result<String, ClientError> {
+        let mut tables = vec![];
+
+        let mut summary = vec![table_row(&["", ""]), table_row(&["---", "---"])];
+        for (name, value) in report::summary_rows(state, report) {
+            summary.push(table_row(&[name, &value]));
+        }
+        tables.push(format!("## Summary\n\n{}", summary.join("\n")));
+
+        let phases = state.timings_seralizer();
+        if !phases.is_empty() {
+            let mut table = vec![
+                table_row(&[
+                    "phase", "count", "min", "mean", "p50", "p90", "p95", "p99", "max",
+                ]),
+                table_row(&["---"; 9]),
+            ];
+            for name in timings::PHASES {
+                if let Some(p) = phases.get(name) {
+                    let ms = [p.min, p.mean, p.p50, p.p90, p.p95, p.p99, p.max]
+                        .map(|v| format!("{}ms", v));
+                    let mut cells = vec![name.to_string(), p.count.to_string()];
+                    cells.extend(ms);
+                    table.push(table_row(&cells));
+                }
+            }
+            tables.push(format!("## Phase timings\n\n{}", table.join("\n")));
+        }
+
+        let rows = report.rows();
+        if !rows.is_empty() {
+            let mut table = vec![
+                table_row(&[
+                    "row", "request", "success", "fail", "error", "p50", "p90", "p99", "max",
+                ]),
+                table_row(&["---"; 9]),
+            ];
+            for row in rows {
+                table.push(table_row(&[
+                    row.row.to_string(),
+                    row.request,
+                    row.success.to_string(),
+                    row.fail.to_string(),
+                    row.error.to_string(),
+                    format!("{}ms", row.p50),
+                    format!("{}ms", row.p90),
+                    format!("{}ms", row.p99),
+                    format!("{}ms", row.max),
+                ]));
+            }
+            tables.push(format!("## Rows\n\n{}", table.join("\n")));
+        }
+
+        return Ok(tables.join("\n\n"));
+    }
+
+    fn interval(&self, interval: &IntervalSerialize, first: bool) -> Result<String, ClientError> {
+        // The csv header and row have the names and values, in order.
+        let csv = interval.to_csv(true).map_err(parse_error)?;
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let header = match reader.headers() {
+            Ok(header) => header.clone(),
+            Err(err) => return Err(ClientError::StateParseError(err.to_string())),
+        };
+        let row = match reader.records().next() {
+            Some(Ok(row)) => row,
+            _ => return Err(ClientError::StateParseError("empty interval".to_string())),
+        };
+
+        let mut lines = vec![];
+        if first {
+            lines.push(table_row(&header.iter().collect::<Vec<&str>>()));
+            lines.push(table_row(&vec!["---"; header.len()]));
+        }
+        lines.push(table_row(&row.iter().collect::<Vec<&str>>()));
+        return Ok(lines.join("\n"));
+    }
+}
+
+/// JUnit XML, for CI test reporters; a testcase per script row. Non 2xx responses are
+/// failures, requests without a response are errors.
+struct JunitFormatter;
+
+impl OutputFormatter for JunitFormatter {
+    fn summary(&self, state: &State, report: &Report) -> Result<String, ClientError> {
+        let rows = report.rows();
+        let errors = rows.iter().filter(|row| row.error > 0).count();
+        let failures = rows
+            .iter()
+            .filter(|row| row.error == 0 && row.fail > 0)
+            .count();
+        let time = state.to_seralizer().took as f64 / 1000.0;
+
+        let mut xml = vec![
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#.to_string(),
+            format!(
+                r#"<testsuites name="noop-client" tests="{}" failures="{}" errors="{}" time="{}">"#,
+                rows.len(),
+                failures,
+                errors,
+                time
+            ),
+            format!(
+                r#"  <testsuite name="noop-client" tests="{}" failures="{}" errors="{}" time="{}">"#,
+                rows.len(),
+                failures,
+                errors,
+                time
+            ),
+            "    <properties>".to_string(),
+        ];
+        for (name, value) in report::summary_rows(state, report) {
+            xml.push(format!(
+                r#"      <property name="{}" value="{}"/>"#,
+                name,
+                xml_escape(&value)
+            ));
+        }
+        xml.push("    </properties>".to_string());
+
+        for row in rows {
+            let requests = row.success + row.fail + row.error;
+            let responses = row.success + row.fail;
+            xml.push(format!(
+                r#"    <testcase name="{}" classname="row {}" time="{}">"#,
+                xml_escape(&row.request),
+                row.row,
+                row.mean * responses as f64 / 1000.0
+            ));
+
+            let failed = format!(
+                "{} of {} responses failed; status codes {}",
+                row.fail, responses, row.codes
+            );
+            if row.error > 0 {
+                let mut message = format!(
+                    "{} of {} requests errored; {}",
+                    row.error, requests, row.errors
+                );
+                if row.fail > 0 {
+                    message = format!("{}; {}", message, failed);
+                }
+                xml.push(format!(
+                    r#"      <error message="{}" type="ClientError"/>"#,
+                    xml_escape(&message)
+                ));
+            } else if row.fail > 0 {
+                xml.push(format!(
+                    r#"      <failure message="{}" type="status"/>"#,
+                    xml_escape(&failed)
+                ));
+            }
+            xml.push("    </testcase>".to_string());
+        }
+
+        xml.push("  </testsuite>".to_string());
+        xml.push("</testsuites>".to_string());
+        return Ok(xml.join("\n"));
+    }
+
+    /// There's nowhere for them in a single document; '--report-interval' isn't valid with
+    /// junit output.
+    fn interval(&self, _: &IntervalSerialize, _: bool) -> Result<String, ClientError> {
+        return Err(ClientError::StateParseError(
+            "junit output has no interval reports".to_string(),
+        ));
+    }
+}
+
+fn xml_escape(value: &str) -> String {
+    return value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;");
+}
+
+fn table_row<S: AsRef<str>>(cells: &[S]) -> String {
+    let cells: Vec<String> = cells
+        .iter()
+        .map(|cell| cell.as_ref().replace('|', "\\|"))
+        .collect();
+    return format!("| {} |", cells.join(" | "));
+}
+
+#[test]
+fn formatter_test() {
+    assert!(formatter("default").is_some());
+    assert!(formatter("csv").is_some());
+    assert!(formatter("markdown").is_some());
+    assert!(formatter("junit").is_some());
+    assert_eq!(formatter("json").is_some(), cfg!(feature = "json"));
+    assert_eq!(formatter("yaml").is_some(), cfg!(feature = "yaml"));
+    assert!(formatter("xml").is_none());
+
+    assert!(names().contains(&"markdown"));
+    assert_eq!(names().contains(&"yaml"), cfg!(feature = "yaml"));
+}
+
+#[test]
+fn markdown_test() {
+    let outcome = crate::state::Outcome {
+        success: 1,
+        code: 200,
+        row: 1,
+        label: "GET http://localhost:3000/a|b".to_string(),
+        timings: Some(timings::Timings {
+            total: std::time::Duration::from_millis(5),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let mut state = State::new(1);
+    state.record(&outcome);
+    let mut report = Report::new(0, None);
+    report.record(&outcome);
+
+    let markdown = MarkdownFormatter.summary(&state, &report).unwrap();
+    assert!(markdown.starts_with("## Summary\n\n|  |  |\n| --- | --- |\n"));
+    assert!(markdown.contains("| success | 1 |"));
+    assert!(markdown.contains("## Phase timings"));
+    assert!(markdown.contains("| 1 | GET http://localhost:3000/a\\|b | 1 | 0 | 0 |"));
+
+    let interval = state.interval();
+    let first = MarkdownFormatter.interval(&interval, true).unwrap();
+    let lines: Vec<&str> = first.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("| elapsed | interval | processed |"));
+    assert_eq!(lines[0].matches('|').count(), lines[2].matches('|').count());
+    assert_eq!(
+        MarkdownFormatter
+            .interval(&interval, false)
+            .unwrap()
+            .lines()
+            .count(),
+        1
+    );
+}
+
+#[cfg(feature = "yaml")]
+#[test]
+fn yaml_test() {
+    let mut state = State::new(1);
+    state.increment(1, 0, 0);
+    let yaml = YamlFormatter
+        .summary(&state, &Report::new(0, None))
+        .unwrap();
+    assert!(yaml.starts_with("---\n"));
+    assert!(yaml.contains("\nprocessed: 1\n"));
+    assert!(yaml.contains("\ntimings: {}"));
+
+    let interval = YamlFormatter.interval(&state.interval(), true).unwrap();
+    assert!(interval.contains("\ntotal_processed: 1\n"));
+}
+
+#[test]
+fn junit_test() {
+    let mut state = State::new(4);
+    let mut report = Report::new(0, None);
+    let outcomes = [
+        (1, 200, 0, ""),
+        (2, 200, 0, ""),
+        (2, 404, 0, ""),
+        (3, 0, 1, "Connection Failed"),
+    ];
+    for (row, code, error, kind) in outcomes {
+        let outcome = crate::state::Outcome {
+            success: (code == 200) as usize,
+            fail: (code == 404) as usize,
+            error,
+            code,
+            row,
+            label: format!("GET http://localhost:3000/{}?a=1&b=2", row),
+            error_kind: kind.to_string(),
+            ..Default::default()
+        };
+        state.record(&outcome);
+        report.record(&outcome);
+    }
+
+    let xml = JunitFormatter.summary(&state, &report).unwrap();
+    assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites name=\"noop-client\" tests=\"3\" failures=\"1\" errors=\"1\""));
+    assert!(xml.contains("<property name=\"processed\" value=\"4\"/>"));
+    assert!(xml.contains("<testcase name=\"GET http://localhost:3000/1?a=1&amp;b=2\" classname=\"row 1\" time=\"0\">\n    </testcase>"));
+    assert!(xml.contains(
+        "<failure message=\"1 of 2 responses failed; status codes 200:1;404:1\" type=\"status\"/>"
+    ));
+    assert!(xml.contains(
+        "<error message=\"1 of 1 requests errored; Connection Failed:1\" type=\"ClientError\"/>"
+    ));
+    assert!(xml.ends_with("</testsuites>"));
+    assert!(JunitFormatter.interval(&state.interval(), true).is_err());
+}
@@ -0,0 +1,705 @@
+//! Lazy request plan.
+//!
+//! Expands script rows into one request per iteration as they're dispatched, so memory
+//! stays flat regardless of how many requests a script asks for; or, with '--mix', draws
+//! each request from the rows at random by weight; with '--shuffle', rows are loaded up
+//! front to be put in a random order. 'DryRun' describes the plan by row instead, for
+//! '--dry-run'.
+
+use crate::client;
+use crate::config::{Config, Rows};
+use crate::errors::ClientError;
+use crate::random;
+
+use std::sync;
+use std::time;
+
+use serde_derive::Serialize;
+
+pub struct Plan {
+    rows: Rows,
+    row: Option<sync::Arc<Config>>,
+    rows_seen: usize,
+    remaining: usize,
+    planned: usize,
+    mix: Option<Mix>,
+    stop: sync::Arc<sync::atomic::AtomicBool>,
+}
+
+impl Plan {
+    pub fn new(config: &Config) -> Result<Plan, ClientError> {
+        let mut plan = Plan {
+            rows: config.rows()?,
+            row: None,
+            rows_seen: 0,
+            remaining: 0,
+            planned: 0,
+            mix: None,
+            stop: sync::Arc::new(sync::atomic::AtomicBool::new(false)),
+        };
+        if config.mix {
+            plan.mix = Some(Mix::new(config, plan.script()?)?);
+        }
+        // Numbered first, so results are by the script's rows.
+        if config.shuffle {
+            let mut rows = plan.script()?;
+            random::Rng::new(config.seed).shuffle(&mut rows);
+            plan.rows = Box::new(rows.into_iter().map(|row| Ok((*row).clone())));
+        }
+        return Ok(plan);
+    }
+
+    /// A plan of rows already loaded; an agent's share of a coordinator's plan. Rows carry
+    /// the run's options, so it's a mix if they're to be drawn from.
+    #[cfg_attr(not(feature = "distributed"), allow(dead_code))]
+    pub fn from_rows(rows: Vec<Config>) -> Result<Plan, ClientError> {
+        let options = rows.first().cloned();
+        let mut plan = Plan {
+            rows: Box::new(rows.into_iter().map(Ok)),
+            row: None,
+            rows_seen: 0,
+            remaining: 0,
+            planned: 0,
+            mix: None,
+            stop: sync::Arc::new(sync::atomic::AtomicBool::new(false)),
+        };
+        if let Some(options) = options.filter(|options| options.mix) {
+            plan.mix = Some(Mix::new(&options, plan.script()?)?);
+        }
+        return Ok(plan);
+    }
+
+    /// Flag to stop the plan early; once set, no more requests are yielded.
+    #[cfg_attr(not(any(feature = "tui", feature = "distributed")), allow(dead_code))]
+    pub fn stopper(&self) -> sync::Arc<sync::atomic::AtomicBool> {
+        return self.stop.clone();
+    }
+
+    /// Number of requests yielded so far.
+    pub fn planned(&self) -> usize {
+        return self.planned;
+    }
+
+    /// Takes the rest of the plan as numbered rows, loading every row up front; for virtual
+    /// users to loop through.
+    pub fn script(&mut self) -> Result<Vec<sync::Arc<Config>>, ClientError> {
+        let mut script = vec![];
+        for row in &mut self.rows {
+            let mut row = row?;
+            self.rows_seen += 1;
+            if row.row == 0 {
+                row.row = self.rows_seen;
+            }
+            script.push(sync::Arc::new(row));
+        }
+        return Ok(script);
+    }
+
+    /// Counts requests made other than by iterating, e.g. by virtual users.
+    pub fn add_planned(&mut self, requests: usize) {
+        self.planned += requests;
+    }
+
+    /// Takes the rest of the plan as a share of each row's iterations per agent, loading
+    /// every row up front. Remainders go to a different agent from row to row, so rows of
+    /// a single request (e.g. replays) are dealt out in turn; agents without a share of a
+    /// row don't get it. Requests are counted as agents make them, rather than here.
+    #[cfg_attr(not(feature = "distributed"), allow(dead_code))]
+    pub fn split(&mut self, agents: usize) -> Result<Vec<Vec<Config>>, ClientError> {
+        let agents = agents.max(1);
+        if let Some(mix) = &self.mix {
+            return Ok(mix.split(agents));
+        }
+
+        let mut shares = vec![vec![]; agents];
+        for row in &mut self.rows {
+            let mut row = row?;
+            self.rows_seen += 1;
+            if row.row == 0 {
+                row.row = self.rows_seen;
+            }
+
+            let (each, rest) = (row.iterations / agents, row.iterations % agents);
+            let first = (self.rows_seen - 1) % agents;
+            for (n, share) in shares.iter_mut().enumerate() {
+                let iterations = each + usize::from((n + agents - first) % agents < rest);
+                if iterations > 0 {
+                    share.push(Config {
+                        iterations,
+                        ..row.clone()
+                    });
+                }
+            }
+        }
+        return Ok(shares);
+    }
+}
+
+impl Iterator for Plan {
+    type Item = Result<sync::Arc<Config>, ClientError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stop.load(sync::atomic::Ordering::Relaxed) {
+            return None;
+        }
+
+        if let Some(mix) = &mut self.mix {
+            let row = mix.draw()?;
+            self.planned += 1;
+            return Some(Ok(row));
+        }
+
+        while self.remaining == 0 {
+            match self.rows.next()? {
+                Ok(mut row) => {
+                    self.rows_seen += 1;
+                    // Rows split from a coordinator's plan come numbered.
+                    if row.row == 0 {
+                        row.row = self.rows_seen;
+                    }
+                    self.remaining = row.iterations;
+                    self.row = Some(sync::Arc::new(row));
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+
+        self.remaining -= 1;
+        self.planned += 1;
+        return self.row.clone().map(Ok);
+    }
+}
+
+/// Rows drawn from at random by weight, for '--mix'; until there's been enough requests, or
+/// the duration's up.
+struct Mix {
+    rows: Vec<sync::Arc<Config>>,
+    /// Running total of row weights.
+    weights: Vec<f64>,
+    /// Requests left; None to run for the duration.
+    remaining: Option<usize>,
+    duration: time::Duration,
+    /// Set on the first draw.
+    deadline: Option<time::Instant>,
+    seed: u64,
+    rng: random::Rng,
+}
+
+impl Mix {
+    fn new(options: &Config, rows: Vec<sync::Arc<Config>>) -> Result<Mix, ClientError> {
+        let weights: Vec<f64> = rows
+            .iter()
+            .scan(0.0, |total, row| {
+                *total += row.weight;
+                Some(*total)
+            })
+            .collect();
+        if weights.last().copied().unwrap_or_default() <= 0.0 {
+            return Err(ClientError::ConfigError(
+                "invalid mix, there are no rows with weight".to_string(),
+            ));
+        }
+
+        return Ok(Mix {
+            remaining: mix_requests(options, rows.iter().map(|row| row.iterations)),
+            rows,
+            weights,
+            duration: options.duration,
+            deadline: None,
+            seed: options.seed,
+            rng: random::Rng::new(options.seed),
+        });
+    }
+
+    fn draw(&mut self) -> Option<sync::Arc<Config>> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+        if !self.duration.is_zero() {
+            let deadline = *self
+                .deadline
+                .get_or_insert_with(|| time::Instant::now() + self.duration);
+            if time::Instant::now() >= deadline {
+                return None;
+            }
+        }
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= 1;
+        }
+
+        let total = self.weights[self.weights.len() - 1];
+        let draw = self.rng.next_f64() * total;
+        let n = self.weights.partition_point(|weight| *weight <= draw);
+        return Some(self.rows[n.min(self.rows.len() - 1)].clone());
+    }
+
+    /// Every row for each agent, with a share of the requests and a seed of its own; agents
+    /// without a share of the requests don't get any rows.
+    #[cfg_attr(not(feature = "distributed"), allow(dead_code))]
+    fn split(&self, agents: usize) -> Vec<Vec<Config>> {
+        return (0..agents)
+            .map(|n| {
+                let requests = self
+                    .remaining
+                    .map(|remaining| remaining / agents + usize::from(n < remaining % agents));
+                if requests == Some(0) {
+                    return vec![];
+                }
+                return self
+                    .rows
+                    .iter()
+                    .map(|row| Config {
+                        requests: requests.unwrap_or_default(),
+                        seed: random::derive(self.seed, n as u64),
+                        ..(**row).clone()
+                    })
+                    .collect();
+            })
+            .collect();
+    }
+}
+
+/// Requests a mix draws; None when it runs for a duration.
+fn mix_requests(options: &Config, iterations: impl Iterator<Item = usize>) -> Option<usize> {
+    return match (options.requests, options.duration.is_zero()) {
+        (0, true) => Some(iterations.sum()),
+        (0, false) => None,
+        (requests, _) => Some(requests),
+    };
+}
+
+/// A script row as it'd be run; times in milliseconds.
+#[derive(Debug, Serialize)]
+pub struct RowPlan {
+    pub row: usize,
+    pub source: String,
+    pub method: String,
+    pub endpoint: String,
+    pub headers: Vec<String>,
+    /// Bytes in the request head, as HTTP/1.1 text; requests have no body.
+    pub request_size: u64,
+    pub requests: usize,
+    pub sleep: u64,
+    /// Sleeps are give or take this much.
+    pub sleep_jitter: u64,
+    /// For virtual users, e.g. 'exp:200ms'.
+    pub think_time: String,
+    /// Share of requests with '--mix', relative to other rows'.
+    pub weight: f64,
+    pub timeout: u64,
+    /// Replay offset from the start of the run.
+    pub offset: u64,
+}
+
+/// What a run would send, without sending it.
+#[derive(Debug, Serialize)]
+pub struct DryRun {
+    pub rows: Vec<RowPlan>,
+    /// Requests per row are about their weight's share with '--mix'; and none are counted
+    /// when it runs for a duration.
+    pub requests: usize,
+    pub pool_size: usize,
+    pub users: usize,
+    pub mix: bool,
+    /// The least the run would take, in milliseconds: least sleeps spread over the pool, the
+    /// last replay offset, or a user's sleeps and least think times. Response times come on top.
+    pub duration: u64,
+}
+
+impl DryRun {
+    pub fn new(config: &Config) -> Result<DryRun, ClientError> {
+        let mut dry_run = DryRun {
+            rows: vec![],
+            requests: 0,
+            pool_size: config.pool_size.max(1),
+            users: config.users,
+            mix: config.mix,
+            duration: 0,
+        };
+
+        // Virtual users each go through every row, a loop at a time.
+        let repeats = match config.users {
+            0 => 1,
+            users => users * config.loops,
+        };
+
+        let mut sleeping = 0;
+        let mut thinking = 0;
+        let mut replaying = time::Duration::ZERO;
+        for (n, row) in config.rows()?.enumerate() {
+            let row = row?;
+            let headers = client::headers(&row)?;
+
+            let sleep = row.sleep_time().min().as_millis() as u64;
+            sleeping += sleep * row.iterations as u64;
+            thinking += (sleep + row.think_time.min().as_millis() as u64) * row.iterations as u64;
+            if row.replay_speed > 0.0 {
+                replaying = replaying
+                    .max(time::Duration::from_millis(row.offset).div_f64(row.replay_speed));
+            }
+
+            dry_run.requests += row.iterations * repeats;
+            dry_run.rows.push(RowPlan {
+                row: n + 1,
+                source: row.source.clone(),
+                method: row.method.clone(),
+                endpoint: row.endpoint.clone(),
+                request_size: client::request_size(&row.method, &row.endpoint, &headers),
+                headers: headers
+                    .iter()
+                    .map(|(name, value)| format!("{}:{}", name, value))
+                    .collect(),
+                requests: row.iterations * repeats,
+                sleep: row.sleep,
+                sleep_jitter: row.sleep_jitter,
+                think_time: row.think_time.to_string(),
+                weight: row.weight,
+                timeout: row.timeout,
+                offset: row.offset,
+            });
+        }
+
+        dry_run.duration = match config.users {
+            0 => (sleeping / dry_run.pool_size as u64).max(replaying.as_millis() as u64),
+            _ => thinking * config.loops as u64,
+        };
+
+        if config.mix {
+            dry_run.mix(config)?;
+        }
+        return Ok(dry_run);
+    }
+
+    /// Requests by row weight, for a mix; with how long they'd take, unless the duration's
+    /// up first.
+    fn mix(&mut self, config: &Config) -> Result<(), ClientError> {
+        let weights: f64 = self.rows.iter().map(|row| row.weight).sum();
+        if weights <= 0.0 {
+            return Err(ClientError::ConfigError(
+                "invalid mix, there are no rows with weight".to_string(),
+            ));
+        }
+
+        let requests = mix_requests(config, self.rows.iter().map(|row| row.requests));
+        let mut sleeping = 0;
+        for row in &mut self.rows {
+            row.requests = match requests {
+                Some(requests) => (requests as f64 * row.weight / weights).round() as usize,
+                None => 0,
+            };
+            sleeping += row.sleep.saturating_sub(row.sleep_jitter) * row.requests as u64;
+        }
+
+        let duration = config.duration.as_millis() as u64;
+        self.requests = requests.unwrap_or_default();
+        self.duration = match (requests, duration) {
+            (None, _) => duration,
+            (Some(_), 0) => sleeping / self.pool_size as u64,
+            (Some(_), _) => (sleeping / self.pool_size as u64).min(duration),
+        };
+        return Ok(());
+    }
+
+    /// A line per row, then one for the run; as key=value pairs.
+    pub fn string(&self) -> String {
+        let mut lines = vec![];
+        for row in &self.rows {
+            let mut line = format!("row={}", row.row);
+            if !row.source.is_empty() {
+                line.push_str(&format!(" source={}", row.source));
+            }
+            line.push_str(&format!(
+                " method={} endpoint={} requests={} sleep={}ms",
+                row.method, row.endpoint, row.requests, row.sleep
+            ));
+            if row.sleep_jitter > 0 {
+                line.push_str(&format!(" sleep_jitter={}ms", row.sleep_jitter));
+            }
+            line.push_str(&format!(
+                " timeout={}ms request_size={}B",
+                row.timeout, row.request_size
+            ));
+            if self.users > 0 {
+                line.push_str(&format!(" think_time={}", row.think_time));
+            }
+            if self.mix {
+                line.push_str(&format!(" weight={}", row.weight));
+            }
+            if row.offset > 0 {
+                line.push_str(&format!(" offset={}ms", row.offset));
+            }
+            if !row.headers.is_empty() {
+                line.push_str(&format!(" headers=\"{}\"", row.headers.join(";")));
+            }
+            lines.push(line);
+        }
+
+        let workers = match self.users {
+            0 => format!("pool_size={}", self.pool_size),
+            users => format!("users={}", users),
+        };
+        lines.push(format!(
+            "rows={} requests={} {} duration={:?}",
+            self.rows.len(),
+            self.requests,
+            workers,
+            time::Duration::from_millis(self.duration)
+        ));
+        return lines.join("\n");
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string(self).expect("failed to seralize json");
+    }
+}
+
+#[test]
+fn plan_test() {
+    let mut c = crate::config::test_config();
+    c.iterations = 3;
+
+    let mut plan = Plan::new(&c).unwrap();
+    assert_eq!(plan.by_ref().count(), 3);
+    assert_eq!(plan.planned(), 3);
+
+    // Fragile - assume project root
+    c.iterations = 1;
+    c.script = "test/test_script.txt".to_string();
+    let plan: Vec<_> = Plan::new(&c).unwrap().map(Result::unwrap).collect();
+    assert_eq!(plan.len(), 11); // 6 + 1 + 1 + 1 + 1 (default) + 1
+    assert_eq!(
+        plan[5].endpoint,
+        "http://localhost:3000/request1".to_string()
+    );
+    assert_eq!(plan[6].method, "POST".to_string());
+    assert_eq!((plan[5].row, plan[6].row), (1, 2));
+    assert!(sync::Arc::ptr_eq(&plan[0], &plan[5]));
+}
+
+#[test]
+fn stopper_test() {
+    let mut c = crate::config::test_config();
+    c.iterations = 10;
+
+    let mut plan = Plan::new(&c).unwrap();
+    let stop = plan.stopper();
+    assert!(plan.next().is_some());
+    stop.store(true, sync::atomic::Ordering::Relaxed);
+    assert!(plan.next().is_none());
+    assert_eq!(plan.planned(), 1);
+}
+
+#[test]
+fn split_test() {
+    // Fragile - assume project root
+    let mut c = crate::config::test_config();
+    c.script = "test/test_script.txt".to_string();
+
+    let mut plan = Plan::new(&c).unwrap();
+    let shares = plan.split(2).unwrap();
+    assert!(plan.next().is_none());
+
+    let rows = |share: &Vec<Config>| -> Vec<(usize, usize)> {
+        return share.iter().map(|c| (c.row, c.iterations)).collect();
+    };
+    assert_eq!(rows(&shares[0]), vec![(1, 3), (3, 1), (5, 1)]);
+    assert_eq!(rows(&shares[1]), vec![(1, 3), (2, 1), (4, 1), (6, 1)]);
+
+    // Agents keep the coordinator's row numbers.
+    let plan: Vec<_> = Plan::from_rows(shares[1].clone())
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(plan.len(), 6);
+    assert_eq!((plan[2].row, plan[3].row, plan[5].row), (1, 2, 6));
+}
+
+#[test]
+fn shuffle_test() {
+    let mut c = crate::config::test_config();
+    c.script = "test/test_script.txt".to_string();
+    c.seed = 42;
+
+    let order = |c: &Config| -> Vec<(usize, String)> {
+        let mut order: Vec<(usize, String)> = Plan::new(c)
+            .unwrap()
+            .map(|request| {
+                let request = request.unwrap();
+                (request.row, request.endpoint.clone())
+            })
+            .collect();
+        order.dedup();
+        return order;
+    };
+    let script = order(&c);
+
+    // Rows keep their numbers, in a different order; the same one for the same seed.
+    c.shuffle = true;
+    let shuffled = order(&c);
+    assert_ne!(shuffled, script);
+    assert_eq!(order(&c), shuffled);
+    let mut sorted = shuffled.clone();
+    sorted.sort();
+    assert_eq!(sorted, script);
+
+    // Agents get rows in the shuffled order, as numbered.
+    let shares = Plan::new(&c).unwrap().split(1).unwrap();
+    let rows: Vec<usize> = shares[0].iter().map(|row| row.row).collect();
+    let mut shuffled_rows: Vec<usize> = shuffled.iter().map(|(row, _)| *row).collect();
+    shuffled_rows.dedup();
+    assert_eq!(rows, shuffled_rows);
+}
+
+#[test]
+fn mix_test() {
+    let mut c = crate::config::test_config();
+    c.mix = true;
+    c.seed = 42;
+    c.requests = 10000;
+
+    let mut rows = vec![];
+    for (endpoint, weight) in [
+        ("/reads", 70.0),
+        ("/searches", 25.0),
+        ("/writes", 5.0),
+        ("/none", 0.0),
+    ] {
+        rows.push(Config {
+            endpoint: format!("http://localhost:3000{}", endpoint),
+            weight,
+            ..c.clone()
+        });
+    }
+
+    let draws = |rows: Vec<Config>| -> Vec<usize> {
+        let mut counts = vec![0; 4];
+        for request in Plan::from_rows(rows).unwrap() {
+            counts[request.unwrap().row - 1] += 1;
+        }
+        return counts;
+    };
+    let counts = draws(rows.clone());
+    assert_eq!(counts.iter().sum::<usize>(), 10000);
+    assert!((6800..7200).contains(&counts[0]), "{:?}", counts);
+    assert!((2300..2700).contains(&counts[1]), "{:?}", counts);
+    assert!((400..600).contains(&counts[2]), "{:?}", counts);
+    assert_eq!(counts[3], 0);
+
+    // The same seed draws the same requests.
+    assert_eq!(draws(rows.clone()), counts);
+    rows[0].seed = 43;
+    assert_ne!(draws(rows.clone()), counts);
+
+    // Or as many as there'd have been iterations, or for a duration.
+    rows[0].requests = 0;
+    assert_eq!(draws(rows.clone()).iter().sum::<usize>(), 4);
+    rows[0].duration = time::Duration::from_millis(50);
+    let start = time::Instant::now();
+    assert!(draws(rows.clone()).iter().sum::<usize>() > 4);
+    assert!(start.elapsed() >= time::Duration::from_millis(50));
+
+    for row in &mut rows {
+        row.weight = 0.0;
+    }
+    assert!(Plan::from_rows(rows).is_err());
+}
+
+#[test]
+fn mix_split_test() {
+    let mut c = crate::config::test_config();
+    c.mix = true;
+    c.seed = 42;
+    c.requests = 5;
+    c.script = "test/test_script.txt".to_string();
+
+    let mut plan = Plan::new(&c).unwrap();
+    let shares = plan.split(3).unwrap();
+    assert!(shares.iter().all(|share| share.len() == 6));
+    assert_eq!(
+        shares
+            .iter()
+            .map(|share| share[0].requests)
+            .collect::<Vec<usize>>(),
+        vec![2, 2, 1]
+    );
+    assert_ne!(shares[0][0].seed, shares[1][0].seed);
+    assert_eq!(
+        (shares[0][5].row, shares[0][5].seed),
+        (6, shares[0][0].seed)
+    );
+
+    // Agents only get rows if they've requests to draw.
+    assert!(Plan::new(&c).unwrap().split(6).unwrap()[5].is_empty());
+}
+
+#[test]
+fn dry_run_test() {
+    let mut c = crate::config::test_config();
+    c.headers = vec!["X-Test:run1".to_string()];
+    c.iterations = 4;
+    c.sleep = 100;
+    c.pool_size = 2;
+
+    let dry_run = DryRun::new(&c).unwrap();
+    assert_eq!(dry_run.requests, 4);
+    assert_eq!(dry_run.duration, 200);
+    assert_eq!(dry_run.rows.len(), 1);
+    assert_eq!(dry_run.rows[0].headers, vec!["X-Test:run1".to_string()]);
+    // "GET / HTTP/1.1\r\n" "Host: www.example.com\r\n" "X-Test: run1\r\n" "\r\n"
+    assert_eq!(dry_run.rows[0].request_size, 16 + 23 + 14 + 2);
+    assert_eq!(
+        dry_run.string(),
+        "row=1 method=GET endpoint=http://www.example.com requests=4 sleep=100ms timeout=0ms request_size=55B headers=\"X-Test:run1\"\nrows=1 requests=4 pool_size=2 duration=200ms"
+    );
+
+    // Fragile - assume project root
+    c.iterations = 1;
+    c.sleep = 0;
+    c.headers = vec![];
+    c.script = "test/test_script.txt".to_string();
+    let dry_run = DryRun::new(&c).unwrap();
+    assert_eq!(dry_run.rows.len(), 6);
+    assert_eq!(dry_run.requests, 11);
+    assert_eq!(dry_run.rows[1].source, "test/test_script.txt:3".to_string());
+    // 6 * 100 + 10 + 10 + 10 over 2
+    assert_eq!(dry_run.duration, 315);
+
+    // Users loop through every row, at least sleeping and thinking before each request.
+    c.users = 3;
+    c.loops = 2;
+    c.think_time = crate::random::Distribution::parse("uniform:50ms,100ms").unwrap();
+    let dry_run = DryRun::new(&c).unwrap();
+    assert_eq!(dry_run.requests, 66);
+    assert_eq!(dry_run.rows[0].requests, 36);
+    // (6 * 100 + 10 + 10 + 10 + 11 * 50) * 2
+    assert_eq!(dry_run.duration, 2360);
+    let string = dry_run.string();
+    assert!(string.contains(" requests=36 sleep=100ms timeout=0ms request_size="));
+    assert!(string.contains(" think_time=uniform:50ms,100ms "));
+    assert!(string.ends_with("\nrows=6 requests=66 users=3 duration=2.36s"));
+
+    // Sleeps can be as short as their jitter takes them.
+    c.sleep_jitter = 40;
+    let dry_run = DryRun::new(&c).unwrap();
+    // (6 * 60 + 0 + 0 + 0 + 11 * 50) * 2
+    assert_eq!(dry_run.duration, 1820);
+    assert!(dry_run.string().contains(" sleep=100ms sleep_jitter=40ms "));
+    c.sleep_jitter = 0;
+
+    // A mix's requests go by weight.
+    c.users = 0;
+    c.think_time = crate::random::Distribution::Constant(time::Duration::ZERO);
+    c.mix = true;
+    c.requests = 100;
+    let dry_run = DryRun::new(&c).unwrap();
+    assert_eq!(dry_run.requests, 100);
+    assert_eq!(dry_run.rows[0].requests, 17);
+    assert!(dry_run.string().contains(" weight=1"));
+    // 17 * 100 + 17 * 10 * 3 over 2
+    assert_eq!(dry_run.duration, 1105);
+    c.requests = 0;
+    c.duration = time::Duration::from_secs(30);
+    let dry_run = DryRun::new(&c).unwrap();
+    assert_eq!((dry_run.requests, dry_run.duration), (0, 30000));
+}
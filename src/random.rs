@@ -0,0 +1,316 @@
+//! Seedable randomness, for think times and request mixes.
+//!
+//! SplitMix64; small, fast and good enough for load shapes, though not for anything
+//! cryptographic. Kept in tree so a seed gives the same numbers from release to release.
+//!
+//! A run's '--seed' is the root of everything drawn: the plan draws from it directly (row
+//! order with '--shuffle', a '--mix'), while workers draw from seeds derived from it; each
+//! virtual user, or each of a pool's workers, dealt requests in turn in plan order.
+//! Agents get derived seeds too, as roots of their shares.
+
+use crate::config::parse_duration;
+
+use std::collections::hash_map;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::time;
+
+use serde_derive::{Deserialize, Serialize};
+
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        return Rng { state: seed };
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        return z ^ (z >> 31);
+    }
+
+    /// Uniform in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        return (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    }
+
+    /// Fisher-Yates.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = (self.next_u64() % (i as u64 + 1)) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+/// A stream per worker of a pool, each from a seed of its own derived from the run's. Draws
+/// go round the workers in turn; so request n draws from worker n's (mod the pool's size)
+/// stream, whichever thread ends up making it.
+pub struct Workers {
+    rngs: Vec<Rng>,
+    next: usize,
+}
+
+impl Workers {
+    pub fn new(seed: u64, size: usize) -> Workers {
+        let rngs = (0..size.max(1) as u64)
+            .map(|worker| Rng::new(derive(seed, worker)))
+            .collect();
+        return Workers { rngs, next: 0 };
+    }
+
+    /// The next worker's stream.
+    pub fn next(&mut self) -> &mut Rng {
+        let worker = self.next;
+        self.next = (worker + 1) % self.rngs.len();
+        return &mut self.rngs[worker];
+    }
+}
+
+/// A seed that differs from run to run.
+pub fn seed() -> u64 {
+    let mut hasher = hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(
+        time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    return hasher.finish();
+}
+
+/// Seed for a seed's nth child, e.g. an agent's share of a run; children differ from each
+/// other and from the parent's own numbers.
+pub fn derive(seed: u64, n: u64) -> u64 {
+    let mut rng = Rng::new(
+        (seed ^ 0x6a09_e667_f3bc_c909).wrapping_add(n.wrapping_mul(0xd1b5_4a32_d192_ed03)),
+    );
+    return rng.next_u64();
+}
+
+/// A distribution of durations, e.g. for think times.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Distribution {
+    Constant(time::Duration),
+    /// Between a min and max.
+    Uniform(time::Duration, time::Duration),
+    /// Mean and standard deviation; negative samples are taken as 0.
+    Normal(time::Duration, time::Duration),
+    /// Mean.
+    Exponential(time::Duration),
+    /// Scale (the least it can be) and shape; the lower the shape, the longer the tail.
+    Pareto(time::Duration, f64),
+}
+
+impl Distribution {
+    /// Parses e.g. '200ms', 'const:200ms', 'uniform:100ms,300ms', 'normal:200ms,50ms',
+    /// 'exp:200ms' or 'pareto:100ms,1.5'; durations as for 'parse_duration'.
+    pub fn parse(value: &str) -> Result<Distribution, String> {
+        let invalid = || format!("invalid distribution: {}", value);
+        let (name, params) = value.trim().split_once(':').unwrap_or(("const", value));
+        let params: Vec<&str> = params.split(',').map(str::trim).collect();
+        let duration = |n: usize| parse_duration(params[n]);
+
+        let distribution = match (name, params.len()) {
+            ("const" | "constant", 1) => Distribution::Constant(duration(0)?),
+            ("uniform", 2) => Distribution::Uniform(duration(0)?, duration(1)?),
+            ("normal", 2) => Distribution::Normal(duration(0)?, duration(1)?),
+            ("exp" | "exponential", 1) => Distribution::Exponential(duration(0)?),
+            ("pareto", 2) => match params[1].parse() {
+                Ok(shape) if shape > 0.0 => Distribution::Pareto(duration(0)?, shape),
+                _ => return Err(invalid()),
+            },
+            _ => return Err(invalid()),
+        };
+
+        if let Distribution::Uniform(min, max) = distribution {
+            if min > max {
+                return Err(invalid());
+            }
+        }
+        return Ok(distribution);
+    }
+
+    pub fn is_zero(&self) -> bool {
+        return *self == Distribution::Constant(time::Duration::ZERO);
+    }
+
+    pub fn sample(&self, rng: &mut Rng) -> time::Duration {
+        let secs = match *self {
+            Distribution::Constant(duration) => return duration,
+            Distribution::Uniform(min, max) => {
+                min.as_secs_f64() + (max - min).as_secs_f64() * rng.next_f64()
+            }
+            // Box-Muller.
+            Distribution::Normal(mean, deviation) => {
+                let (u, v) = (1.0 - rng.next_f64(), rng.next_f64());
+                let z = (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos();
+                mean.as_secs_f64() + deviation.as_secs_f64() * z
+            }
+            Distribution::Exponential(mean) => -(1.0 - rng.next_f64()).ln() * mean.as_secs_f64(),
+            Distribution::Pareto(scale, shape) => {
+                scale.as_secs_f64() / (1.0 - rng.next_f64()).powf(1.0 / shape)
+            }
+        };
+        return time::Duration::try_from_secs_f64(secs.max(0.0)).unwrap_or(time::Duration::MAX);
+    }
+
+    /// The least a sample can be.
+    pub fn min(&self) -> time::Duration {
+        return match *self {
+            Distribution::Constant(duration) => duration,
+            Distribution::Uniform(min, _) => min,
+            Distribution::Normal(..) | Distribution::Exponential(_) => time::Duration::ZERO,
+            Distribution::Pareto(scale, _) => scale,
+        };
+    }
+}
+
+impl fmt::Display for Distribution {
+    /// As parsed.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = |duration: time::Duration| format!("{}ms", duration.as_secs_f64() * 1000.0);
+        match *self {
+            Distribution::Constant(duration) => return write!(f, "{}", ms(duration)),
+            Distribution::Uniform(min, max) => return write!(f, "uniform:{},{}", ms(min), ms(max)),
+            Distribution::Normal(mean, deviation) => {
+                return write!(f, "normal:{},{}", ms(mean), ms(deviation))
+            }
+            Distribution::Exponential(mean) => return write!(f, "exp:{}", ms(mean)),
+            Distribution::Pareto(scale, shape) => {
+                return write!(f, "pareto:{},{}", ms(scale), shape)
+            }
+        };
+    }
+}
+
+#[test]
+fn rng_test() {
+    let (mut a, mut b) = (Rng::new(42), Rng::new(42));
+    let first: Vec<u64> = (0..4).map(|_| a.next_u64()).collect();
+    assert_eq!(first, (0..4).map(|_| b.next_u64()).collect::<Vec<u64>>());
+    assert_ne!(first[0], Rng::new(43).next_u64());
+
+    let mut rng = Rng::new(7);
+    let mean = (0..10000).map(|_| rng.next_f64()).sum::<f64>() / 10000.0;
+    assert!((0.48..0.52).contains(&mean), "mean {}", mean);
+}
+
+#[test]
+fn shuffle_test() {
+    let shuffled = |seed: u64| {
+        let mut items: Vec<usize> = (0..20).collect();
+        Rng::new(seed).shuffle(&mut items);
+        return items;
+    };
+
+    let items = shuffled(42);
+    assert_eq!(items, shuffled(42));
+    assert_ne!(items, shuffled(43));
+    assert_ne!(items, (0..20).collect::<Vec<usize>>());
+
+    let mut sorted = items.clone();
+    sorted.sort();
+    assert_eq!(sorted, (0..20).collect::<Vec<usize>>());
+}
+
+#[test]
+fn derive_test() {
+    assert_eq!(derive(42, 1), derive(42, 1));
+    assert_ne!(derive(42, 1), derive(42, 2));
+    assert_ne!(derive(42, 0), Rng::new(42).next_u64());
+    assert_ne!(derive(42, 0), derive(43, 0));
+}
+
+#[test]
+fn workers_test() {
+    let mut workers = Workers::new(42, 2);
+    let draws: Vec<u64> = (0..4).map(|_| workers.next().next_u64()).collect();
+
+    let (mut first, mut second) = (Rng::new(derive(42, 0)), Rng::new(derive(42, 1)));
+    assert_eq!(
+        draws,
+        vec![
+            first.next_u64(),
+            second.next_u64(),
+            first.next_u64(),
+            second.next_u64()
+        ]
+    );
+
+    // A pool has at least a worker.
+    assert_eq!(
+        Workers::new(42, 0).next().next_u64(),
+        Rng::new(derive(42, 0)).next_u64()
+    );
+}
+
+#[test]
+fn parse_test() {
+    let ms = time::Duration::from_millis;
+    assert_eq!(
+        Distribution::parse("200ms"),
+        Ok(Distribution::Constant(ms(200)))
+    );
+    assert!(Distribution::parse("0").unwrap().is_zero());
+    assert_eq!(
+        Distribution::parse("uniform:100ms, 300ms"),
+        Ok(Distribution::Uniform(ms(100), ms(300)))
+    );
+    assert_eq!(
+        Distribution::parse("exp:1.5s"),
+        Ok(Distribution::Exponential(ms(1500)))
+    );
+    assert_eq!(
+        Distribution::parse("pareto:100ms,1.5"),
+        Ok(Distribution::Pareto(ms(100), 1.5))
+    );
+
+    for invalid in [
+        "exp",
+        "exp:200ms,1s",
+        "uniform:300ms,100ms",
+        "pareto:100ms,0",
+        "poisson:1s",
+        "normal:1x,1s",
+    ] {
+        assert!(Distribution::parse(invalid).is_err(), "{}", invalid);
+    }
+
+    for value in [
+        "200ms",
+        "uniform:100ms,300ms",
+        "normal:200ms,50ms",
+        "exp:200ms",
+    ] {
+        assert_eq!(Distribution::parse(value).unwrap().to_string(), value);
+    }
+    assert_eq!(
+        Distribution::parse("pareto:0.1s,1.5").unwrap().to_string(),
+        "pareto:100ms,1.5"
+    );
+}
+
+#[test]
+fn sample_test() {
+    let ms = time::Duration::from_millis;
+    let mean = |distribution: Distribution| {
+        let mut rng = Rng::new(1);
+        let samples: Vec<time::Duration> =
+            (0..20000).map(|_| distribution.sample(&mut rng)).collect();
+        assert!(samples.iter().all(|sample| *sample >= distribution.min()));
+        return samples.iter().sum::<time::Duration>().as_secs_f64() * 1000.0 / 20000.0;
+    };
+
+    assert_eq!(mean(Distribution::Constant(ms(200))), 200.0);
+    assert!((195.0..205.0).contains(&mean(Distribution::Uniform(ms(100), ms(300)))));
+    assert!((195.0..205.0).contains(&mean(Distribution::Normal(ms(200), ms(50)))));
+    assert!((190.0..210.0).contains(&mean(Distribution::Exponential(ms(200)))));
+    // Shape * scale / (shape - 1)
+    assert!((140.0..160.0).contains(&mean(Distribution::Pareto(ms(100), 3.0))));
+}
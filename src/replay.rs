@@ -0,0 +1,378 @@
+//! Access log replay.
+//!
+//! Parses common / combined access logs (see 'log_format main' in examples/nginx.conf) and,
+//! with the json feature, JSON lines access logs; turning each logged request into a
+//! single iteration 'Config' row pointed at '--endpoint', as the log's read.
+
+use crate::config::{Config, Rows};
+use crate::errors::ClientError;
+
+use std::fs;
+use std::io::{self, BufRead};
+use std::iter;
+
+static MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+#[derive(Debug, PartialEq)]
+struct Entry {
+    timestamp: i64, // milliseconds since epoch
+    method: String,
+    path: String,
+}
+
+/// Streams a log's requests as rows, a line at a time, so memory stays flat however long
+/// the log; offsets are from the first request's timestamp.
+pub fn from_access_log(config: &Config) -> Result<Rows, ClientError> {
+    let file = match fs::File::open(&config.replay) {
+        Ok(file) => file,
+        Err(_) => {
+            return Err(ClientError::ConfigError(
+                "invalid replay file path".to_string(),
+            ))
+        }
+    };
+
+    let mut replay = Replay {
+        config: config.clone(),
+        lines: io::BufReader::new(file).lines().enumerate(),
+        first: 0,
+        skipped: 0,
+        pending: None,
+    };
+
+    // The first request's needed up front, for the offsets of the rest.
+    match replay.next_entry() {
+        Some(Ok(entry)) => {
+            replay.first = entry.timestamp;
+            replay.pending = Some(entry);
+        }
+        Some(Err(err)) => return Err(err),
+        None => {
+            return Err(ClientError::ConfigError(format!(
+                "no replayable requests found in {}",
+                config.replay
+            )))
+        }
+    }
+
+    return Ok(Box::new(replay));
+}
+
+struct Replay {
+    config: Config,
+    lines: iter::Enumerate<io::Lines<io::BufReader<fs::File>>>,
+    first: i64,
+    skipped: usize,
+    /// Read ahead to find the first timestamp.
+    pending: Option<Entry>,
+}
+
+impl Replay {
+    /// The next line that parses as a request; lines that don't are skipped and counted.
+    fn next_entry(&mut self) -> Option<Result<Entry, ClientError>> {
+        for (i, line) in &mut self.lines {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    return Some(Err(ClientError::ConfigError(format!(
+                        "{}:{}: {}",
+                        self.config.replay,
+                        i + 1,
+                        err
+                    ))))
+                }
+            };
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            match parse_line(line) {
+                Some(entry) => return Some(Ok(entry)),
+                None => {
+                    self.skipped += 1;
+                    if self.config.debug {
+                        println!(
+                            "DEBUG:: skipping {}:{}: {}",
+                            self.config.replay,
+                            i + 1,
+                            line
+                        );
+                    }
+                }
+            }
+        }
+
+        // Reported once, at the end of the log.
+        if self.skipped > 0 && self.config.errors {
+            eprintln!("replay=\"{}\" skipped={}", self.config.replay, self.skipped);
+        }
+        self.skipped = 0;
+        return None;
+    }
+
+    fn row(&self, entry: Entry) -> Config {
+        let mut c = self.config.clone();
+        c.iterations = 1;
+        c.sleep = 0;
+        c.method = entry.method;
+        c.endpoint = rewrite(&self.config.endpoint, &entry.path);
+        c.offset = (entry.timestamp - self.first).max(0) as u64;
+        return c;
+    }
+}
+
+impl Iterator for Replay {
+    type Item = Result<Config, ClientError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = match self.pending.take() {
+            Some(entry) => entry,
+            None => match self.next_entry()? {
+                Ok(entry) => entry,
+                Err(err) => return Some(Err(err)),
+            },
+        };
+        return Some(Ok(self.row(entry)));
+    }
+}
+
+fn parse_line(line: &str) -> Option<Entry> {
+    #[cfg(feature = "json")]
+    if line.starts_with('{') {
+        return parse_json(line);
+    }
+
+    return parse_clf(line);
+}
+
+/// Parses common and combined log format lines, e.g.
+/// 127.0.0.1 - - [10/Oct/2000:13:55:36 -0700] "GET /index.html HTTP/1.0" 200 2326 "-" "curl/8.0"
+fn parse_clf(line: &str) -> Option<Entry> {
+    let (_, rest) = line.split_once('[')?;
+    let (time, rest) = rest.split_once(']')?;
+    let (_, rest) = rest.split_once('"')?;
+    let (request, _) = rest.split_once('"')?;
+
+    let (method, path) = parse_request(request)?;
+    return Some(Entry {
+        timestamp: parse_clf_time(time)?,
+        method,
+        path,
+    });
+}
+
+/// Parses JSON lines logs, accepting the common nginx / Apache field names.
+#[cfg(feature = "json")]
+fn parse_json(line: &str) -> Option<Entry> {
+    let value: serde_json::Value = serde_json::from_str(line).ok()?;
+    let field = |names: &[&str]| -> Option<String> {
+        names
+            .iter()
+            .find_map(|n| value.get(*n).and_then(|v| v.as_str()).map(str::to_string))
+    };
+
+    let (method, path) = match field(&["request"]) {
+        Some(request) => parse_request(&request)?,
+        None => (
+            field(&["method", "request_method"])?.to_uppercase(),
+            field(&["path", "uri", "request_uri", "url"])?,
+        ),
+    };
+
+    let timestamp = if let Some(time) = field(&["time_local"]) {
+        parse_clf_time(&time)?
+    } else if let Some(time) = field(&["time_iso8601", "time", "timestamp", "@timestamp"]) {
+        parse_rfc3339(&time)?
+    } else {
+        let secs = ["msec", "time", "timestamp"]
+            .iter()
+            .find_map(|n| value.get(*n).and_then(|v| v.as_f64()))?;
+        (secs * 1000.0) as i64
+    };
+
+    return Some(Entry {
+        timestamp,
+        method,
+        path: strip_host(&path).to_string(),
+    });
+}
+
+fn parse_request(request: &str) -> Option<(String, String)> {
+    let mut parts = request.split_whitespace();
+    let method = parts.next()?;
+    let path = parts.next()?;
+
+    if !method.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    return Some((method.to_uppercase(), strip_host(path).to_string()));
+}
+
+/// Drops scheme and host from absolute-form request targets, as seen in proxy logs.
+fn strip_host(target: &str) -> &str {
+    if let Some((_, rest)) = target.split_once("://") {
+        return match rest.find('/') {
+            Some(i) => &rest[i..],
+            None => "/",
+        };
+    }
+    return target;
+}
+
+fn rewrite(endpoint: &str, path: &str) -> String {
+    let base = endpoint.trim_end_matches('/');
+    if path.starts_with('/') {
+        return format!("{}{}", base, path);
+    }
+    return format!("{}/{}", base, path);
+}
+
+/// Parses '10/Oct/2000:13:55:36 -0700' to milliseconds since epoch.
+fn parse_clf_time(time: &str) -> Option<i64> {
+    let (datetime, zone) = time.trim().split_once(' ')?;
+    let mut parts = datetime.splitn(4, [':', '/']);
+
+    let day: i64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let millis = parse_hms(parts.next()?)?;
+
+    let offset = parse_zone(zone)?;
+    return Some(days_from_civil(year, month, day) * 86_400_000 + millis - offset);
+}
+
+/// Parses '2000-10-10T13:55:36.123-07:00' (or 'Z') to milliseconds since epoch.
+#[cfg(feature = "json")]
+fn parse_rfc3339(time: &str) -> Option<i64> {
+    let (date, time) = time.trim().split_once(['T', ' '])?;
+    let mut date = date.splitn(3, '-');
+    let year: i64 = date.next()?.parse().ok()?;
+    let month: i64 = date.next()?.parse().ok()?;
+    let day: i64 = date.next()?.parse().ok()?;
+
+    let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+        Some(i) => (&time[..i], parse_zone(&time[i..])?),
+        None => (time, 0),
+    };
+
+    return Some(days_from_civil(year, month, day) * 86_400_000 + parse_hms(time)? - offset);
+}
+
+fn parse_hms(time: &str) -> Option<i64> {
+    let mut parts = time.splitn(3, ':');
+    let h: i64 = parts.next()?.parse().ok()?;
+    let m: i64 = parts.next()?.parse().ok()?;
+    let s: f64 = parts.next()?.parse().ok()?;
+    return Some((h * 3600 + m * 60) * 1000 + (s * 1000.0).round() as i64);
+}
+
+/// Parses '-0700', '+07:00' or 'Z' to an offset in milliseconds.
+fn parse_zone(zone: &str) -> Option<i64> {
+    if zone.eq_ignore_ascii_case("z") {
+        return Some(0);
+    }
+
+    let sign = match zone.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = zone[1..].chars().filter(|c| *c != ':').collect();
+    if digits.len() != 4 {
+        return None;
+    }
+    let h: i64 = digits[..2].parse().ok()?;
+    let m: i64 = digits[2..].parse().ok()?;
+    return Some(sign * (h * 3600 + m * 60) * 1000);
+}
+
+/// Days since 1970-01-01; REF: http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    return era * 146_097 + doe - 719_468;
+}
+
+#[test]
+fn parse_clf_test() {
+    let line = r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif?a=1 HTTP/1.0" 200 2326 "http://www.example.com/start.html" "Mozilla/4.08""#;
+    let entry = parse_clf(line).unwrap();
+    assert_eq!(entry.method, "GET".to_string());
+    assert_eq!(entry.path, "/apache_pb.gif?a=1".to_string());
+    assert_eq!(entry.timestamp, 971_211_336_000);
+
+    assert!(parse_clf(r#"127.0.0.1 - - [10/Oct/2000:13:55:36 -0700] "-" 400 0"#).is_none());
+    assert!(parse_clf("garbage").is_none());
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn parse_json_test() {
+    let entry = parse_json(
+        r#"{"time_iso8601":"2000-10-10T20:55:36+00:00","request":"POST /a HTTP/1.1","status":201}"#,
+    )
+    .unwrap();
+    assert_eq!(entry.method, "POST".to_string());
+    assert_eq!(entry.path, "/a".to_string());
+    assert_eq!(entry.timestamp, 971_211_336_000);
+
+    let entry = parse_json(r#"{"msec":971211336.5,"method":"get","uri":"/b"}"#).unwrap();
+    assert_eq!(entry.method, "GET".to_string());
+    assert_eq!(entry.path, "/b".to_string());
+    assert_eq!(entry.timestamp, 971_211_336_500);
+}
+
+#[test]
+fn time_test() {
+    assert_eq!(parse_clf_time("01/Jan/1970:00:00:00 +0000"), Some(0));
+    assert_eq!(parse_clf_time("01/Jan/1970:01:00:00 +01:00"), Some(0));
+    assert!(parse_clf_time("10/Foo/2000:13:55:36 -0700").is_none());
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn parse_rfc3339_test() {
+    assert_eq!(
+        parse_clf_time("10/Oct/2000:13:55:36 -0700"),
+        parse_rfc3339("2000-10-10T20:55:36Z")
+    );
+    assert_eq!(parse_rfc3339("1970-01-01T00:00:01.250+00:00"), Some(1250));
+}
+
+#[test]
+fn rewrite_test() {
+    assert_eq!(
+        rewrite("http://localhost:3000/", "/foo?bar=1"),
+        "http://localhost:3000/foo?bar=1".to_string()
+    );
+    assert_eq!(strip_host("https://example.com/foo"), "/foo");
+    assert_eq!(strip_host("https://example.com"), "/");
+    assert_eq!(strip_host("/foo"), "/foo");
+}
+
+#[test]
+fn from_access_log_test() {
+    let mut c = crate::config::test_config();
+    c.endpoint = "http://localhost:3000".to_string();
+    c.replay = "test/test_access.log".to_string();
+
+    let v: Vec<Config> = from_access_log(&c)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(v.len(), 5);
+    assert_eq!(v[0].offset, 0);
+    assert_eq!(v[1].method, "POST".to_string());
+    assert_eq!(v[1].endpoint, "http://localhost:3000/request2".to_string());
+    assert_eq!(v[4].offset, 2000);
+}
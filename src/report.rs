@@ -0,0 +1,638 @@
+//! Self-contained HTML report for '--report'.
+//!
+//! Kept by the housekeeping thread alongside 'State', bucketing responses by the second
+//! they finished in; written once the run's done, with charts as inline SVG so the file can
+//! be passed around on its own.
+
+use crate::errors::ClientError;
+use crate::histogram::Histogram;
+use crate::state::{self, Outcome, State};
+use crate::timings;
+
+use std::collections;
+use std::fmt::Write;
+use std::fs;
+
+use serde_derive::{Deserialize, Serialize};
+
+static WIDTH: f64 = 800.0;
+static HEIGHT: f64 = 240.0;
+static PAD: f64 = 64.0;
+
+/// Seconds further back than this are settled; their latency histograms summarized.
+static SETTLE_AFTER: usize = 5;
+
+static STYLE: &str = "body{font-family:sans-serif;margin:2em auto;max-width:900px;color:#222}\
+table{border-collapse:collapse;margin:1em 0}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:right}\
+th:first-child,td:first-child{text-align:left}\
+figcaption{font-weight:bold;margin:1em 0 .5em}\
+.legend span{margin-right:1em}\
+code{background:#f4f4f4;padding:2px 4px}";
+
+#[derive(Default)]
+struct Second {
+    responses: u64,
+    errors: u64,
+    bytes_received: u64,
+    latency: Histogram,
+    /// p50, p90 and p99 in milliseconds, once settled.
+    settled: Option<[f64; 3]>,
+}
+
+impl Second {
+    fn percentiles(&self) -> [f64; 3] {
+        if let Some(settled) = self.settled {
+            return settled;
+        }
+        return [50.0, 90.0, 99.0].map(|p| ms(self.latency.percentile(p)));
+    }
+}
+
+/// A script row's results; latencies in milliseconds.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct RowSerialize {
+    pub row: usize,
+    pub request: String,
+    pub success: usize,
+    pub fail: usize,
+    pub error: usize,
+    /// Responses by status code and errors by kind, e.g. '200:10;404:1'
+    pub codes: String,
+    pub errors: String,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+/// A script row's results so far; also shown live by the dashboard.
+#[derive(Default)]
+pub struct RowStats {
+    pub label: String,
+    pub success: usize,
+    pub fail: usize,
+    pub error: usize,
+    /// Responses by status code, and errors by kind.
+    pub codes: collections::BTreeMap<String, usize>,
+    pub errors: collections::BTreeMap<String, usize>,
+    pub latency: Histogram,
+}
+
+impl RowStats {
+    pub fn record(&mut self, outcome: &Outcome) {
+        if self.label.is_empty() {
+            self.label = outcome.label.clone();
+        }
+        self.success += outcome.success;
+        self.fail += outcome.fail;
+        self.error += outcome.error;
+
+        if outcome.error > 0 {
+            *self.errors.entry(outcome.error_kind.clone()).or_default() += 1;
+        } else {
+            *self.codes.entry(outcome.code.to_string()).or_default() += 1;
+        }
+        if let Some(timings) = &outcome.timings {
+            self.latency.record(timings.total);
+        }
+    }
+}
+
+pub struct Report {
+    /// Start of the run, in milliseconds since the epoch.
+    start: u64,
+    /// The run's '--seed', to make it again; unknown for a past run's results file.
+    seed: Option<u64>,
+    /// By second since the start; kept sparse, so an outlying timestamp costs a second, not
+    /// every second up to it.
+    seconds: collections::BTreeMap<usize, Second>,
+    latency: Histogram,
+    codes: collections::BTreeMap<u16, usize>,
+    errors: collections::BTreeMap<String, usize>,
+    rows: collections::BTreeMap<usize, RowStats>,
+}
+
+impl Report {
+    pub fn new(start: u64, seed: Option<u64>) -> Report {
+        return Report {
+            start,
+            seed,
+            seconds: collections::BTreeMap::new(),
+            latency: Histogram::new(),
+            codes: collections::BTreeMap::new(),
+            errors: collections::BTreeMap::new(),
+            rows: collections::BTreeMap::new(),
+        };
+    }
+
+    pub fn record(&mut self, outcome: &Outcome) {
+        let total = outcome.timings.as_ref().map(|timings| timings.total);
+        let end = outcome.timestamp + total.map_or(0, |total| total.as_millis() as u64);
+        let second = (end.saturating_sub(self.start) / 1000) as usize;
+        if let collections::btree_map::Entry::Vacant(entry) = self.seconds.entry(second) {
+            entry.insert(Second::default());
+            self.settle();
+        }
+
+        self.rows.entry(outcome.row).or_default().record(outcome);
+
+        let bucket = self.seconds.entry(second).or_default();
+        if outcome.error > 0 {
+            bucket.errors += 1;
+            *self.errors.entry(outcome.error_kind.clone()).or_default() += 1;
+        } else {
+            bucket.responses += 1;
+            *self.codes.entry(outcome.code).or_default() += 1;
+        }
+        bucket.bytes_received += outcome.bytes_received;
+
+        if let Some(total) = total {
+            self.latency.record(total);
+            // Stragglers into settled seconds still count, but can't move percentiles.
+            if bucket.settled.is_none() {
+                bucket.latency.record(total);
+            }
+        }
+    }
+
+    /// Summarizes seconds that are unlikely to see more responses; so memory stays flat
+    /// over long runs.
+    fn settle(&mut self) {
+        let last = self.seconds.keys().next_back().map_or(0, |last| last + 1);
+        let upto = last.saturating_sub(SETTLE_AFTER);
+        for second in self
+            .seconds
+            .range_mut(..upto)
+            .rev()
+            .map(|(_, second)| second)
+        {
+            if second.settled.is_some() {
+                break;
+            }
+            second.settled = Some(second.percentiles());
+            second.latency = Histogram::new();
+        }
+    }
+
+    pub fn write(&self, state: &State, file: &str) -> Result<(), ClientError> {
+        return match fs::write(file, self.to_html(state)) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(ClientError::ConfigError(format!(
+                "failed to write report '{}': {}",
+                file, err
+            ))),
+        };
+    }
+
+    pub fn to_html(&self, state: &State) -> String {
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>noop-client report</title><style>{}</style></head><body>\n",
+            STYLE
+        );
+        let _ = writeln!(html, "<h1>noop-client report</h1>");
+        let args: Vec<String> = std::env::args().collect();
+        let _ = writeln!(html, "<p><code>{}</code></p>", escape(&args.join(" ")));
+
+        html.push_str(&summary_table(state, self));
+        html.push_str(&phases_table(state));
+
+        let seconds = |f: &dyn Fn(&Second) -> f64| {
+            return self.seconds.iter().map(|(n, s)| (*n, f(s))).collect();
+        };
+        html.push_str(&line_chart(
+            "Throughput over time",
+            "/s",
+            &[
+                ("responses", "#2a7", seconds(&|s| s.responses as f64)),
+                ("errors", "#c33", seconds(&|s| s.errors as f64)),
+            ],
+        ));
+        html.push_str(&line_chart(
+            "Received over time",
+            "MB/s",
+            &[(
+                "received",
+                "#37c",
+                seconds(&|s| s.bytes_received as f64 / 1_000_000.0),
+            )],
+        ));
+        html.push_str(&line_chart(
+            "Latency over time",
+            "ms",
+            &[
+                ("p50", "#2a7", seconds(&|s| s.percentiles()[0])),
+                ("p90", "#e90", seconds(&|s| s.percentiles()[1])),
+                ("p99", "#c33", seconds(&|s| s.percentiles()[2])),
+            ],
+        ));
+
+        let percentiles: Vec<(String, f64, &str)> = [50.0, 75.0, 90.0, 95.0, 99.0, 99.9]
+            .iter()
+            .map(|p| (format!("p{}", p), ms(self.latency.percentile(*p)), "#37c"))
+            .chain([("max".to_string(), ms(self.latency.max()), "#c33")])
+            .collect();
+        html.push_str(&bar_chart("Latency percentiles", "ms", &percentiles));
+
+        let breakdown: Vec<(String, f64, &str)> = self
+            .codes
+            .iter()
+            .map(|(code, count)| {
+                let color = match code {
+                    200..=299 => "#2a7",
+                    300..=399 => "#37c",
+                    _ => "#e90",
+                };
+                (code.to_string(), *count as f64, color)
+            })
+            .chain(
+                self.errors
+                    .iter()
+                    .map(|(kind, count)| (kind.clone(), *count as f64, "#c33")),
+            )
+            .collect();
+        html.push_str(&bar_chart("Status codes and errors", "", &breakdown));
+
+        html.push_str(&self.rows_table());
+        html.push_str("</body></html>\n");
+        return html;
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        return self.seed;
+    }
+
+    /// Results by script row, in row order.
+    pub fn rows(&self) -> Vec<RowSerialize> {
+        return self
+            .rows
+            .iter()
+            .map(|(n, row)| RowSerialize {
+                row: *n,
+                request: row.label.clone(),
+                success: row.success,
+                fail: row.fail,
+                error: row.error,
+                codes: state::count_string(&row.codes),
+                errors: state::count_string(&row.errors),
+                mean: ms(row.latency.mean()),
+                p50: ms(row.latency.percentile(50.0)),
+                p90: ms(row.latency.percentile(90.0)),
+                p99: ms(row.latency.percentile(99.0)),
+                max: ms(row.latency.max()),
+            })
+            .collect();
+    }
+
+    fn rows_table(&self) -> String {
+        let mut html = String::from(
+            "<h2>Rows</h2>\n<table><tr><th>row</th><th>request</th><th>success</th><th>fail</th><th>error</th><th>p50</th><th>p90</th><th>p99</th><th>max</th></tr>\n",
+        );
+        for row in self.rows() {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}ms</td><td>{}ms</td><td>{}ms</td><td>{}ms</td></tr>",
+                row.row,
+                escape(&row.request),
+                row.success,
+                row.fail,
+                row.error,
+                row.p50,
+                row.p90,
+                row.p99,
+                row.max,
+            );
+        }
+        html.push_str("</table>\n");
+        return html;
+    }
+}
+
+/// Summary as (name, value) pairs, with units; for tables.
+pub fn summary_rows(state: &State, report: &Report) -> Vec<(&'static str, String)> {
+    let s = state.to_seralizer();
+    let mut rows = vec![
+        ("duration", format!("{}ms", s.took)),
+        ("requested", s.requested.to_string()),
+        ("processed", s.processed.to_string()),
+        ("success", s.success.to_string()),
+        ("fail", s.fail.to_string()),
+        ("error", s.error.to_string()),
+        ("received", format!("{}B", s.bytes_received)),
+        ("sent", format!("{}B", s.bytes_sent)),
+        ("throughput", format!("{:.2}MB/s", s.throughput)),
+        ("protocols", s.protocols),
+    ];
+    if !s.encodings.is_empty() {
+        rows.push(("compressed", format!("{}B", s.bytes_compressed)));
+        rows.push(("decompressed", format!("{}B", s.bytes_decompressed)));
+        rows.push(("encodings", s.encodings));
+    }
+    if let Some(seed) = report.seed {
+        rows.push(("seed", seed.to_string()));
+    }
+    return rows;
+}
+
+fn summary_table(state: &State, report: &Report) -> String {
+    let mut html = String::from("<h2>Summary</h2>\n<table>\n");
+    for (name, value) in summary_rows(state, report) {
+        let _ = writeln!(
+            html,
+            "<tr><th>{}</th><td>{}</td></tr>",
+            name,
+            escape(&value)
+        );
+    }
+    html.push_str("</table>\n");
+    return html;
+}
+
+fn phases_table(state: &State) -> String {
+    let phases = state.timings_seralizer();
+    if phases.is_empty() {
+        return String::new();
+    }
+
+    let mut html = String::from(
+        "<h2>Phase timings</h2>\n<table><tr><th>phase</th><th>count</th><th>min</th><th>mean</th><th>p50</th><th>p90</th><th>p95</th><th>p99</th><th>max</th></tr>\n",
+    );
+    for name in timings::PHASES {
+        if let Some(p) = phases.get(name) {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}ms</td><td>{}ms</td><td>{}ms</td><td>{}ms</td><td>{}ms</td><td>{}ms</td><td>{}ms</td></tr>",
+                name, p.count, p.min, p.mean, p.p50, p.p90, p.p95, p.p99, p.max
+            );
+        }
+    }
+    html.push_str("</table>\n");
+    return html;
+}
+
+/// A series over elapsed seconds, as (name, colour, values by second).
+type Series<'a> = (&'a str, &'a str, Vec<(usize, f64)>);
+
+/// Seconds a series hasn't got are 0.
+fn line_chart(title: &str, unit: &str, series: &[Series]) -> String {
+    let n = series
+        .iter()
+        .filter_map(|(_, _, v)| v.last().map(|(i, _)| i + 1))
+        .max()
+        .unwrap_or(0);
+    let max = series
+        .iter()
+        .flat_map(|(_, _, v)| v.iter().map(|(_, v)| *v))
+        .fold(0.0, f64::max);
+    let top = if max > 0.0 { max } else { 1.0 };
+
+    let x = |i: usize| PAD + i as f64 * (WIDTH - 2.0 * PAD) / (n.max(2) - 1) as f64;
+    let y = |v: f64| HEIGHT - PAD - v / top * (HEIGHT - 2.0 * PAD);
+
+    let mut svg = format!(
+        "<figure><figcaption>{}</figcaption>\n<svg viewBox=\"0 0 {} {}\" width=\"{}\" height=\"{}\" xmlns=\"http://www.w3.org/2000/svg\" font-size=\"12\">\n",
+        escape(title),
+        WIDTH,
+        HEIGHT,
+        WIDTH,
+        HEIGHT
+    );
+    let _ = writeln!(
+        svg,
+        "<line x1=\"{p}\" y1=\"{b}\" x2=\"{r}\" y2=\"{b}\" stroke=\"#888\"/><line x1=\"{p}\" y1=\"{p}\" x2=\"{p}\" y2=\"{b}\" stroke=\"#888\"/>",
+        p = PAD,
+        b = HEIGHT - PAD,
+        r = WIDTH - PAD
+    );
+    let _ = writeln!(
+        svg,
+        "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}{}</text><text x=\"{}\" y=\"{}\" text-anchor=\"end\">0</text>",
+        PAD - 4.0,
+        PAD + 4.0,
+        round(top),
+        unit,
+        PAD - 4.0,
+        HEIGHT - PAD + 4.0
+    );
+    let _ = writeln!(
+        svg,
+        "<text x=\"{}\" y=\"{}\">0s</text><text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}s</text>",
+        PAD,
+        HEIGHT - PAD + 16.0,
+        WIDTH - PAD,
+        HEIGHT - PAD + 16.0,
+        n.saturating_sub(1)
+    );
+
+    if n == 0 {
+        let _ = writeln!(
+            svg,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">no data</text>",
+            WIDTH / 2.0,
+            HEIGHT / 2.0
+        );
+    }
+    for (_, color, values) in series {
+        let mut points: Vec<String> = vec![];
+        let mut next = 0;
+        for (i, v) in values {
+            // Down to 0 and back across seconds without any.
+            if *i > next {
+                points.push(format!("{:.1},{:.1}", x(next), y(0.0)));
+                points.push(format!("{:.1},{:.1}", x(i - 1), y(0.0)));
+            }
+            points.push(format!("{:.1},{:.1}", x(*i), y(*v)));
+            next = i + 1;
+        }
+        let _ = writeln!(
+            svg,
+            "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\" points=\"{}\"/>",
+            color,
+            points.join(" ")
+        );
+    }
+    svg.push_str("</svg>\n<div class=\"legend\">");
+    for (name, color, _) in series {
+        let _ = write!(
+            svg,
+            "<span style=\"color:{}\">&#9632; {}</span>",
+            color,
+            escape(name)
+        );
+    }
+    svg.push_str("</div></figure>\n");
+    return svg;
+}
+
+/// Horizontal bars, as (label, value, colour).
+fn bar_chart(title: &str, unit: &str, bars: &[(String, f64, &str)]) -> String {
+    let label_width = 200.0;
+    let bar_height = 24.0;
+    let height = bar_height * bars.len().max(1) as f64;
+    let max = bars.iter().map(|(_, v, _)| *v).fold(0.0, f64::max);
+    let top = if max > 0.0 { max } else { 1.0 };
+    let scale = (WIDTH - label_width - 100.0) / top;
+
+    let mut svg = format!(
+        "<figure><figcaption>{}</figcaption>\n<svg viewBox=\"0 0 {} {}\" width=\"{}\" height=\"{}\" xmlns=\"http://www.w3.org/2000/svg\" font-size=\"12\">\n",
+        escape(title),
+        WIDTH,
+        height,
+        WIDTH,
+        height
+    );
+    if bars.is_empty() {
+        let _ = writeln!(svg, "<text x=\"0\" y=\"16\">no data</text>");
+    }
+    for (i, (label, value, color)) in bars.iter().enumerate() {
+        let top = i as f64 * bar_height;
+        let _ = writeln!(
+            svg,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text><rect x=\"{}\" y=\"{}\" width=\"{:.1}\" height=\"{}\" fill=\"{}\"/><text x=\"{:.1}\" y=\"{}\">{}{}</text>",
+            label_width - 8.0,
+            top + 16.0,
+            escape(label),
+            label_width,
+            top + 4.0,
+            value * scale,
+            bar_height - 8.0,
+            color,
+            label_width + value * scale + 4.0,
+            top + 16.0,
+            round(*value),
+            unit
+        );
+    }
+    svg.push_str("</svg></figure>\n");
+    return svg;
+}
+
+fn ms(micros: u64) -> f64 {
+    return micros as f64 / 1000.0;
+}
+
+/// To 3 significant-ish places, for axis and bar labels.
+fn round(value: f64) -> f64 {
+    return (value * 1000.0).round() / 1000.0;
+}
+
+fn escape(value: &str) -> String {
+    return value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+}
+
+#[test]
+fn record_test() {
+    let mut report = Report::new(10_000, None);
+    let outcome = |timestamp: u64, millis: u64| Outcome {
+        success: 1,
+        code: 200,
+        row: 1,
+        label: "GET http://localhost:3000/".to_string(),
+        timestamp,
+        timings: Some(timings::Timings {
+            total: std::time::Duration::from_millis(millis),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    report.record(&outcome(10_000, 10));
+    report.record(&outcome(11_500, 600));
+    report.record(&Outcome {
+        error: 1,
+        row: 2,
+        error_kind: "Connection Failed".to_string(),
+        timestamp: 10_100,
+        ..Default::default()
+    });
+
+    assert_eq!(report.seconds.len(), 2);
+    assert_eq!(report.seconds[&0].responses, 1);
+    assert_eq!(report.seconds[&0].errors, 1);
+    assert_eq!(report.seconds[&2].responses, 1);
+    assert_eq!(report.codes.get(&200), Some(&2));
+    assert_eq!(report.errors.get("Connection Failed"), Some(&1));
+    assert_eq!(report.rows[&1].success, 2);
+    assert_eq!(report.rows[&2].errors.get("Connection Failed"), Some(&1));
+
+    // Far enough on, the first seconds are settled.
+    report.record(&outcome(20_000, 10));
+    assert!(report.seconds[&0].settled.is_some());
+    assert!(report.seconds[&10].settled.is_none());
+    assert_eq!(report.seconds[&0].latency.count(), 0);
+    assert!(report.seconds[&0].percentiles()[0] > 9.0);
+
+    // An outlying timestamp is a second of its own, rather than every second up to it.
+    report.record(&outcome(u64::MAX / 2, 10));
+    assert_eq!(report.seconds.len(), 4);
+    assert!(report.to_html(&State::new(1)).contains("<polyline"));
+}
+
+#[test]
+fn to_html_test() {
+    let mut state = State::new(1);
+    let outcome = Outcome {
+        fail: 1,
+        code: 404,
+        row: 1,
+        label: "GET http://localhost:3000/<404>".to_string(),
+        timings: Some(timings::Timings {
+            total: std::time::Duration::from_millis(5),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    state.record(&outcome);
+    let mut report = Report::new(0, Some(42));
+    report.record(&outcome);
+
+    let html = report.to_html(&state);
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<tr><th>fail</th><td>1</td></tr>"));
+    assert!(html.contains("<tr><th>seed</th><td>42</td></tr>"));
+    assert!(html.contains("GET http://localhost:3000/&lt;404&gt;"));
+    assert!(html.contains("<figcaption>Latency over time</figcaption>"));
+    assert!(html.contains("<polyline"));
+    assert!(html.ends_with("</html>\n"));
+}
+
+#[test]
+fn chart_test() {
+    let empty = line_chart("Empty", "ms", &[("p50", "#2a7", vec![])]);
+    assert!(empty.contains("no data"));
+
+    let chart = line_chart("Line", "ms", &[("p50", "#2a7", vec![(0, 0.0), (1, 2.0)])]);
+    assert!(chart.contains(&format!(
+        "points=\"{:.1},{:.1} {:.1},{:.1}\"",
+        PAD,
+        HEIGHT - PAD,
+        WIDTH - PAD,
+        PAD
+    )));
+
+    // Seconds between are 0.
+    let chart = line_chart("Gap", "ms", &[("p50", "#2a7", vec![(0, 2.0), (3, 2.0)])]);
+    let points = chart
+        .split("points=\"")
+        .nth(1)
+        .unwrap()
+        .split('"')
+        .next()
+        .unwrap();
+    assert_eq!(points.split(' ').count(), 4);
+    assert!(points.contains(&format!(
+        " {:.1},{:.1} ",
+        PAD + (WIDTH - 2.0 * PAD) / 3.0,
+        HEIGHT - PAD
+    )));
+
+    let bars = bar_chart("Bars", "", &[("200".to_string(), 10.0, "#2a7")]);
+    assert!(bars.contains(">10</text>"));
+}
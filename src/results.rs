@@ -0,0 +1,367 @@
+//! Per request results for '--results-file', and re-summarizing them with '--summarize'.
+//!
+//! Records are written as csv or json lines (with features), by the file's extension, from
+//! a writer thread of its own; so a slow disk holds up neither workers nor housekeeping.
+
+use crate::errors::ClientError;
+use crate::report::Report;
+use crate::state::{Outcome, State};
+use crate::timings;
+
+use std::fs;
+use std::io;
+use std::path;
+use std::sync;
+use std::thread;
+use std::time;
+
+#[cfg(feature = "json")]
+use std::io::{BufRead, Write};
+
+use serde_derive::{Deserialize, Serialize};
+
+/// A request's result; status is 0 and latency empty when there's no response.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// When the request was sent, in milliseconds since the epoch.
+    pub timestamp: u64,
+    pub row: usize,
+    pub method: String,
+    pub url: String,
+    pub status: u16,
+    pub protocol: String,
+    pub error: String,
+    /// Milliseconds, from sending to the end of the response body.
+    pub latency: Option<f64>,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub worker: usize,
+}
+
+impl Record {
+    pub fn new(outcome: &Outcome) -> Record {
+        return Record {
+            timestamp: outcome.timestamp,
+            row: outcome.row,
+            method: outcome.method.clone(),
+            url: outcome.endpoint.clone(),
+            status: outcome.code,
+            protocol: outcome.protocol.clone(),
+            error: outcome.error_kind.clone(),
+            latency: outcome
+                .timings
+                .as_ref()
+                .map(|timings| timings.total.as_micros() as f64 / 1000.0),
+            bytes_received: outcome.bytes_received,
+            bytes_sent: outcome.bytes_sent,
+            worker: outcome.worker,
+        };
+    }
+
+    /// Back to an outcome, as far as a record goes; latency's the only phase kept.
+    fn to_outcome(&self) -> Outcome {
+        let mut outcome = Outcome {
+            row: self.row,
+            label: format!("{} {}", self.method, self.url),
+            method: self.method.clone(),
+            endpoint: self.url.clone(),
+            timestamp: self.timestamp,
+            worker: self.worker,
+            code: self.status,
+            protocol: self.protocol.clone(),
+            error_kind: self.error.clone(),
+            bytes_received: self.bytes_received,
+            bytes_sent: self.bytes_sent,
+            ..Default::default()
+        };
+
+        if self.status == 0 {
+            outcome.error = 1;
+        } else if (200..300).contains(&self.status) {
+            outcome.success = 1;
+        } else {
+            outcome.fail = 1;
+        }
+
+        if let Some(latency) = self.latency {
+            outcome.timings = Some(timings::Timings {
+                total: time::Duration::from_secs_f64(latency / 1000.0),
+                ..Default::default()
+            });
+        }
+        return outcome;
+    }
+
+    /// When the response finished.
+    fn end(&self) -> u64 {
+        return self.timestamp + self.latency.unwrap_or_default() as u64;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Csv,
+    #[cfg(feature = "json")]
+    Jsonl,
+}
+
+fn format(file: &str) -> Result<Format, ClientError> {
+    let ext = path::Path::new(file)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    return match ext {
+        "csv" => Ok(Format::Csv),
+        #[cfg(feature = "json")]
+        "jsonl" | "ndjson" => Ok(Format::Jsonl),
+        _ => Err(ClientError::ConfigError(format!(
+            "invalid results file extension '{}'",
+            file
+        ))),
+    };
+}
+
+/// Writes records for '--results-file' from a thread of its own; until dropped.
+pub struct Writer {
+    tx: Option<sync::mpsc::Sender<Record>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Writer {
+    pub fn new(file: &str) -> Result<Writer, ClientError> {
+        let format = format(file)?;
+        let out = match fs::File::create(file) {
+            Ok(out) => io::BufWriter::new(out),
+            Err(err) => {
+                return Err(ClientError::ConfigError(format!(
+                    "invalid results file '{}': {}",
+                    file, err
+                )))
+            }
+        };
+
+        let (tx, rx) = sync::mpsc::channel();
+        let file = file.to_string();
+        let thread = thread::spawn(move || {
+            if let Err(err) = write(out, format, rx) {
+                eprintln!(
+                    "{}",
+                    ClientError::StateParseError(format!("{}: {}", file, err))
+                );
+            }
+        });
+
+        return Ok(Writer {
+            tx: Some(tx),
+            thread: Some(thread),
+        });
+    }
+
+    pub fn write(&self, outcome: &Outcome) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(Record::new(outcome));
+        }
+    }
+}
+
+impl Drop for Writer {
+    /// Waits on what's queued being written out.
+    fn drop(&mut self) {
+        drop(self.tx.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn write(
+    out: io::BufWriter<fs::File>,
+    format: Format,
+    rx: sync::mpsc::Receiver<Record>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        Format::Csv => {
+            let mut wtr = csv::Writer::from_writer(out);
+            for record in rx {
+                wtr.serialize(record)?;
+            }
+            wtr.flush()?;
+        }
+        #[cfg(feature = "json")]
+        Format::Jsonl => {
+            let mut out = out;
+            for record in rx {
+                serde_json::to_writer(&mut out, &record)?;
+                out.write_all(b"\n")?;
+            }
+            out.flush()?;
+        }
+    }
+    return Ok(());
+}
+
+/// Reads a results file back.
+pub fn read(file: &str) -> Result<Vec<Record>, ClientError> {
+    let format = format(file)?;
+    let error = |line: usize, err: &dyn std::fmt::Display| {
+        ClientError::ConfigError(format!("{}:{}: {}", file, line, err))
+    };
+
+    let reader = match fs::File::open(file) {
+        Ok(reader) => io::BufReader::new(reader),
+        Err(_) => {
+            return Err(ClientError::ConfigError(format!(
+                "invalid results file path '{}'",
+                file
+            )))
+        }
+    };
+
+    let mut records = vec![];
+    match format {
+        Format::Csv => {
+            for (i, record) in csv::Reader::from_reader(reader).deserialize().enumerate() {
+                // Line numbers past the header.
+                records.push(record.map_err(|err| error(i + 2, &err))?);
+            }
+        }
+        #[cfg(feature = "json")]
+        Format::Jsonl => {
+            for (i, line) in reader.lines().enumerate() {
+                let line = line.map_err(|err| error(i + 1, &err))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                records.push(serde_json::from_str(&line).map_err(|err| error(i + 1, &err))?);
+            }
+        }
+    }
+    return Ok(records);
+}
+
+/// State as it would've been at the end of the run the records came from.
+pub fn summarize(records: &[Record]) -> State {
+    let mut state = State::new(records.len());
+    for record in records {
+        state.record(&record.to_outcome());
+    }
+
+    let start = records
+        .iter()
+        .map(|r| r.timestamp)
+        .min()
+        .unwrap_or_default();
+    let end = records.iter().map(Record::end).max().unwrap_or_default();
+    state.set_duration(time::Duration::from_millis(end - start));
+    return state;
+}
+
+/// Report on the run the records came from.
+pub fn report(records: &[Record]) -> Report {
+    let start = records
+        .iter()
+        .map(|r| r.timestamp)
+        .min()
+        .unwrap_or_default();
+    let mut report = Report::new(start, None);
+    for record in records {
+        report.record(&record.to_outcome());
+    }
+    return report;
+}
+
+#[test]
+fn record_test() {
+    let outcome = Outcome {
+        fail: 1,
+        code: 404,
+        protocol: "HTTP/1.1".to_string(),
+        row: 2,
+        label: "GET http://localhost:3000/404".to_string(),
+        method: "GET".to_string(),
+        endpoint: "http://localhost:3000/404".to_string(),
+        timestamp: 1000,
+        worker: 3,
+        bytes_received: 74,
+        bytes_sent: 40,
+        timings: Some(timings::Timings {
+            total: time::Duration::from_micros(1500),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let record = Record::new(&outcome);
+    assert_eq!(record.latency, Some(1.5));
+    assert_eq!(record.url, "http://localhost:3000/404".to_string());
+    assert_eq!(record.end(), 1001);
+
+    let back = record.to_outcome();
+    assert_eq!((back.fail, back.code, back.row), (1, 404, 2));
+    assert_eq!(back.label, outcome.label);
+    assert_eq!(
+        back.timings.unwrap().total,
+        time::Duration::from_micros(1500)
+    );
+
+    let error = Record {
+        error: "Connection Failed".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(error.to_outcome().error, 1);
+}
+
+#[test]
+fn format_test() {
+    assert_eq!(format("results.csv").unwrap(), Format::Csv);
+    assert_eq!(format("results.jsonl").is_ok(), cfg!(feature = "json"));
+    assert!(format("results.txt").is_err());
+    assert!(format("results").is_err());
+}
+
+#[test]
+fn write_read_test() {
+    let mut exts = vec!["csv"];
+    if cfg!(feature = "json") {
+        exts.push("jsonl");
+    }
+
+    for ext in exts {
+        let file = std::env::temp_dir().join(format!(
+            "noop-client-results-{}.{}",
+            std::process::id(),
+            ext
+        ));
+        let file = file.to_str().unwrap();
+
+        let writer = Writer::new(file).unwrap();
+        writer.write(&Outcome {
+            success: 1,
+            code: 200,
+            timestamp: 1000,
+            timings: Some(timings::Timings {
+                total: time::Duration::from_millis(20),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        writer.write(&Outcome {
+            error: 1,
+            error_kind: "Connection Failed".to_string(),
+            timestamp: 1500,
+            ..Default::default()
+        });
+        drop(writer);
+
+        let records = read(file).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].latency, Some(20.0));
+        assert_eq!(records[1].latency, None);
+
+        let state = summarize(&records);
+        assert!(state
+            .string()
+            .starts_with("requested=2 processed=2 success=1 fail=0 error=1 duration=500ms"));
+        let _ = fs::remove_file(file);
+    }
+}
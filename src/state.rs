@@ -1,28 +1,263 @@
+use crate::client;
+use crate::config::Config;
+use crate::errors::ClientError;
+use crate::histogram::Histogram;
+use crate::report::Report;
+#[cfg(any(feature = "json", feature = "yaml"))]
+use crate::report::RowSerialize;
+use crate::threadpool;
+use crate::timings;
+
+use std::collections;
 use std::sync;
 use std::time;
 
 use csv;
-use serde_derive::Serialize;
+use serde_derive::{Deserialize, Serialize};
+
+/// Outcome of a single request, sent from a worker to the housekeeping thread; or from an
+/// agent to its coordinator.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Outcome {
+    pub success: usize,
+    pub fail: usize,
+    pub error: usize,
+    pub code: u16,
+    pub protocol: String,
+    /// Phase timings, when a response was received.
+    pub timings: Option<timings::Timings>,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    /// Content encoding with '--compressed', along with the body size as received and
+    /// decoded.
+    pub encoding: String,
+    pub body_size: u64,
+    pub decoded_size: u64,
+    /// Script row the request came from, e.g. 'GET http://localhost:3000/'
+    pub row: usize,
+    pub label: String,
+    pub method: String,
+    pub endpoint: String,
+    /// When the request was sent, in milliseconds since the epoch; and the worker it ran on.
+    pub timestamp: u64,
+    pub worker: usize,
+    /// Kind of error, e.g. 'Connection Failed'; without the detail, so they can be counted.
+    pub error_kind: String,
+}
+
+impl Outcome {
+    pub fn new(request: &Config, result: &Result<client::Response, ClientError>) -> Outcome {
+        let mut outcome = Outcome {
+            row: request.row,
+            label: format!("{} {}", request.method, request.endpoint),
+            method: request.method.clone(),
+            endpoint: request.endpoint.clone(),
+            worker: threadpool::worker(),
+            ..Default::default()
+        };
+        let mut sent = time::SystemTime::now();
+        match result {
+            Ok(response) => {
+                outcome.code = response.status;
+                outcome.protocol = response.protocol.clone();
+                outcome.timings = Some(response.timings.clone());
+                sent -= response.timings.total;
+                outcome.bytes_received = response.bytes_received;
+                outcome.bytes_sent = response.bytes_sent;
+                outcome.encoding = response.encoding.clone();
+                outcome.body_size = response.body_size;
+                outcome.decoded_size = response.decoded_size;
+                if (200..300).contains(&response.status) {
+                    outcome.success = 1;
+                } else {
+                    outcome.fail = 1;
+                }
+            }
+            Err(err) => {
+                outcome.error = 1;
+                outcome.error_kind = err.kind();
+            }
+        }
+        outcome.timestamp = sent
+            .duration_since(time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        return outcome;
+    }
+}
+
+pub enum Message {
+    /// Sent as a request starts, with '--tui' or '--metrics-addr'; to show what's in flight.
+    Started,
+    Outcome(Box<Outcome>),
+    /// Sent once the plan has been fully dispatched, with the number of requests made.
+    Planned(usize),
+    /// Sent when a coordinator loses an agent, as its outcomes won't come.
+    #[cfg_attr(not(feature = "distributed"), allow(dead_code))]
+    Kill,
+}
 
 pub struct State {
     start: time::Instant,
     requested: usize,
+    planned: bool,
     processed: usize,
     success: usize,
     fail: usize,
     error: usize,
+    bytes_received: u64,
+    bytes_sent: u64,
+    bytes_compressed: u64,
+    bytes_decompressed: u64,
+    protocols: collections::BTreeMap<String, usize>,
+    encodings: collections::BTreeMap<String, usize>,
+    /// One histogram per phase, in 'timings::PHASES' order.
+    phases: Vec<Histogram>,
+    interval: Interval,
     killed: bool,
+    /// Fixed, for a summary of a past run; otherwise time since 'start'.
+    duration: Option<time::Duration>,
     mux: sync::Mutex<()>,
 }
 
+/// Counts since the last interval report.
+struct Interval {
+    start: time::Instant,
+    processed: usize,
+    success: usize,
+    fail: usize,
+    error: usize,
+    latency: Histogram,
+}
+
+impl Interval {
+    fn new() -> Self {
+        return Interval {
+            start: time::Instant::now(),
+            processed: 0,
+            success: 0,
+            fail: 0,
+            error: 0,
+            latency: Histogram::new(),
+        };
+    }
+}
+
+/// Interval report, for the interval and cumulative; latencies in milliseconds.
 #[derive(Serialize)]
-pub struct StateSerialize {
-    took: u128,
-    requested: usize,
+pub struct IntervalSerialize {
+    elapsed: u128,
+    interval: u128,
     processed: usize,
     success: usize,
     fail: usize,
     error: usize,
+    rps: f64,
+    p50: f64,
+    p90: f64,
+    p99: f64,
+    max: f64,
+    total_processed: usize,
+    total_success: usize,
+    total_fail: usize,
+    total_error: usize,
+    total_rps: f64,
+    total_p50: f64,
+    total_p99: f64,
+}
+
+impl IntervalSerialize {
+    pub fn string(&self) -> String {
+        return format!(
+            "elapsed={}ms interval={}ms processed={} success={} fail={} error={} rps={:.1} p50={}ms p90={}ms p99={}ms max={}ms total_processed={} total_success={} total_fail={} total_error={} total_rps={:.1} total_p50={}ms total_p99={}ms",
+            self.elapsed,
+            self.interval,
+            self.processed,
+            self.success,
+            self.fail,
+            self.error,
+            self.rps,
+            self.p50,
+            self.p90,
+            self.p99,
+            self.max,
+            self.total_processed,
+            self.total_success,
+            self.total_fail,
+            self.total_error,
+            self.total_rps,
+            self.total_p50,
+            self.total_p99,
+        );
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string(self).expect("failed to seralize json");
+    }
+
+    #[cfg(feature = "yaml")]
+    pub fn to_yaml(&self) -> Result<String, Box<dyn std::error::Error>> {
+        return Ok(serde_yaml::to_string(self)?.trim().to_owned());
+    }
+
+    /// A csv row, with the header row ahead of it when 'header'.
+    pub fn to_csv(&self, header: bool) -> Result<String, Box<dyn std::error::Error>> {
+        let mut wtrb = csv::WriterBuilder::new();
+        wtrb.has_headers(header);
+        let mut wtr = wtrb.from_writer(vec![]);
+        wtr.serialize(self)?;
+        return Ok(String::from_utf8(wtr.into_inner()?)?.trim().to_owned());
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct StateSerialize {
+    pub took: u128,
+    pub requested: usize,
+    pub processed: usize,
+    pub success: usize,
+    pub fail: usize,
+    pub error: usize,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub throughput: f64,
+    pub bytes_compressed: u64,
+    pub bytes_decompressed: u64,
+    pub protocols: String,
+    pub encodings: String,
+}
+
+/// Phase percentiles, in milliseconds.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PhaseSerialize {
+    pub count: u64,
+    pub min: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+#[cfg(any(feature = "json", feature = "yaml"))]
+#[derive(Serialize)]
+pub struct StateJsonSerialize {
+    #[serde(flatten)]
+    state: StateSerialize,
+    timings: collections::BTreeMap<&'static str, PhaseSerialize>,
+    /// Total latencies (in milliseconds) with their counts; so runs can be compared.
+    latencies: Vec<(f64, u64)>,
+    rows: Vec<RowSerialize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+/// The csv summary's last column.
+#[derive(Serialize)]
+struct SeedSerialize {
+    seed: Option<u64>,
 }
 
 impl State {
@@ -30,26 +265,122 @@ impl State {
         State {
             start: time::Instant::now(),
             requested: r,
+            planned: true,
             processed: 0,
             success: 0,
             fail: 0,
             error: 0,
+            bytes_received: 0,
+            bytes_sent: 0,
+            bytes_compressed: 0,
+            bytes_decompressed: 0,
+            protocols: collections::BTreeMap::new(),
+            encodings: collections::BTreeMap::new(),
+            phases: vec![Histogram::new(); timings::PHASES.len()],
+            interval: Interval::new(),
             killed: false,
+            duration: None,
             mux: sync::Mutex::new(()),
         }
     }
 
+    /// State for a plan that's still being dispatched; 'requested' counts up until 'plan'.
+    pub fn streaming() -> Self {
+        let mut state = State::new(0);
+        state.planned = false;
+        return state;
+    }
+
+    pub fn plan(&mut self, requested: usize) {
+        let _lock = self.mux.lock();
+        self.requested = requested;
+        self.planned = true;
+    }
+
+    pub fn record(&mut self, outcome: &Outcome) {
+        if !self.planned {
+            self.requested = self.requested.max(self.processed + 1);
+        }
+        if !outcome.protocol.is_empty() {
+            let _lock = self.mux.lock();
+            *self.protocols.entry(outcome.protocol.clone()).or_default() += 1;
+        }
+        if outcome.bytes_received > 0 || outcome.bytes_sent > 0 {
+            let _lock = self.mux.lock();
+            self.bytes_received += outcome.bytes_received;
+            self.bytes_sent += outcome.bytes_sent;
+        }
+        if !outcome.encoding.is_empty() {
+            let _lock = self.mux.lock();
+            *self.encodings.entry(outcome.encoding.clone()).or_default() += 1;
+            self.bytes_compressed += outcome.body_size;
+            self.bytes_decompressed += outcome.decoded_size;
+        }
+        if let Some(timings) = &outcome.timings {
+            let _lock = self.mux.lock();
+            self.interval.latency.record(timings.total);
+            for (histogram, phase) in self.phases.iter_mut().zip(timings.phases()) {
+                if let Some(phase) = phase {
+                    histogram.record(phase);
+                }
+            }
+        }
+        self.increment(outcome.success, outcome.fail, outcome.error);
+    }
+
     pub fn increment(&mut self, success: usize, fail: usize, error: usize) {
         let _lock = self.mux.lock();
         self.processed += 1;
         self.success += success;
         self.fail += fail;
         self.error += error;
+
+        self.interval.processed += 1;
+        self.interval.success += success;
+        self.interval.fail += fail;
+        self.interval.error += error;
+    }
+
+    /// Reports on the interval since the last report, and starts the next.
+    pub fn interval(&mut self) -> IntervalSerialize {
+        let _lock = self.mux.lock();
+        let now = time::Instant::now();
+        let interval = std::mem::replace(&mut self.interval, Interval::new());
+        let took = now - interval.start;
+        let elapsed = now - self.start;
+
+        let ms = |micros: u64| micros as f64 / 1000.0;
+        let rps = |processed: usize, took: time::Duration| match took.as_secs_f64() {
+            secs if secs > 0.0 => processed as f64 / secs,
+            _ => 0.0,
+        };
+        let total = &self.phases[timings::PHASES.len() - 1];
+
+        return IntervalSerialize {
+            elapsed: elapsed.as_millis(),
+            interval: took.as_millis(),
+            processed: interval.processed,
+            success: interval.success,
+            fail: interval.fail,
+            error: interval.error,
+            rps: rps(interval.processed, took),
+            p50: ms(interval.latency.percentile(50.0)),
+            p90: ms(interval.latency.percentile(90.0)),
+            p99: ms(interval.latency.percentile(99.0)),
+            max: ms(interval.latency.max()),
+            total_processed: self.processed,
+            total_success: self.success,
+            total_fail: self.fail,
+            total_error: self.error,
+            total_rps: rps(self.processed, elapsed),
+            total_p50: ms(total.percentile(50.0)),
+            total_p99: ms(total.percentile(99.0)),
+        };
     }
 
     pub fn done(&self) -> bool {
         let _lock = self.mux.lock();
-        self.killed || self.requested == self.processed
+        self.killed || (self.planned && self.requested == self.processed)
     }
 
     pub fn kill(&mut self) {
@@ -59,15 +390,54 @@ impl State {
 
     pub fn string(&self) -> String {
         let _lock = self.mux.lock();
-        let duration = time::Instant::now() - self.start;
-        return format!(
+        let duration = self.duration();
+        let mut string = format!(
             "requested={} processed={} success={} fail={} error={} duration={:?}",
             self.requested, self.processed, self.success, self.fail, self.error, duration,
         );
+        if self.bytes_received > 0 || self.bytes_sent > 0 {
+            string.push_str(&format!(
+                " received={}B sent={}B throughput={:.2}MB/s",
+                self.bytes_received,
+                self.bytes_sent,
+                self.throughput(duration),
+            ));
+        }
+        if !self.protocols.is_empty() {
+            string.push_str(&format!(" protocols={}", count_string(&self.protocols)));
+        }
+        if !self.encodings.is_empty() {
+            string.push_str(&format!(
+                " compressed={}B decompressed={}B encodings={}",
+                self.bytes_compressed,
+                self.bytes_decompressed,
+                count_string(&self.encodings),
+            ));
+        }
+        return string;
     }
 
-    fn to_seralizer(&self) -> StateSerialize {
-        let took = time::Instant::now() - self.start;
+    pub fn set_duration(&mut self, duration: time::Duration) {
+        self.duration = Some(duration);
+    }
+
+    fn duration(&self) -> time::Duration {
+        return self
+            .duration
+            .unwrap_or_else(|| time::Instant::now() - self.start);
+    }
+
+    /// Bytes received per second, in MB/s.
+    fn throughput(&self, duration: time::Duration) -> f64 {
+        let secs = duration.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        return self.bytes_received as f64 / secs / 1_000_000.0;
+    }
+
+    pub fn to_seralizer(&self) -> StateSerialize {
+        let took = self.duration();
         return StateSerialize {
             took: took.as_millis(),
             requested: self.requested,
@@ -75,23 +445,98 @@ impl State {
             success: self.success,
             fail: self.fail,
             error: self.error,
+            bytes_received: self.bytes_received,
+            bytes_sent: self.bytes_sent,
+            throughput: self.throughput(took),
+            bytes_compressed: self.bytes_compressed,
+            bytes_decompressed: self.bytes_decompressed,
+            protocols: count_string(&self.protocols),
+            encodings: count_string(&self.encodings),
         };
     }
 
+    /// Percentiles for phases with timings, by phase name.
+    pub fn timings_seralizer(&self) -> collections::BTreeMap<&'static str, PhaseSerialize> {
+        let ms = |micros: u64| micros as f64 / 1000.0;
+        return timings::PHASES
+            .iter()
+            .zip(&self.phases)
+            .filter(|(_, h)| h.count() > 0)
+            .map(|(name, h)| {
+                let phase = PhaseSerialize {
+                    count: h.count(),
+                    min: ms(h.min()),
+                    mean: ms(h.mean()),
+                    p50: ms(h.percentile(50.0)),
+                    p90: ms(h.percentile(90.0)),
+                    p95: ms(h.percentile(95.0)),
+                    p99: ms(h.percentile(99.0)),
+                    max: ms(h.max()),
+                };
+                (*name, phase)
+            })
+            .collect();
+    }
+
+    /// Total latencies, in milliseconds, with their counts.
+    #[cfg(any(feature = "json", feature = "yaml"))]
+    fn latencies_seralizer(&self) -> Vec<(f64, u64)> {
+        return self.phases[timings::PHASES.len() - 1]
+            .buckets()
+            .into_iter()
+            .map(|(micros, count)| (micros as f64 / 1000.0, count))
+            .collect();
+    }
+
     #[cfg(feature = "json")]
-    pub fn to_json(&self) -> String {
-        return serde_json::to_string(&self.to_seralizer()).expect("failed to seralize json");
+    pub fn to_json(&self, report: &Report) -> String {
+        let json = StateJsonSerialize {
+            state: self.to_seralizer(),
+            timings: self.timings_seralizer(),
+            latencies: self.latencies_seralizer(),
+            rows: report.rows(),
+            seed: report.seed(),
+        };
+        return serde_json::to_string(&json).expect("failed to seralize json");
+    }
+
+    /// Same shape as json.
+    #[cfg(feature = "yaml")]
+    pub fn to_yaml(&self, report: &Report) -> Result<String, Box<dyn std::error::Error>> {
+        let yaml = StateJsonSerialize {
+            state: self.to_seralizer(),
+            timings: self.timings_seralizer(),
+            latencies: self.latencies_seralizer(),
+            rows: report.rows(),
+            seed: report.seed(),
+        };
+        return Ok(serde_yaml::to_string(&yaml)?.trim().to_owned());
     }
 
-    pub fn to_csv(&self) -> Result<String, Box<dyn std::error::Error>> {
+    /// A header and a row; with the run's seed last, empty where it's unknown.
+    pub fn to_csv(&self, report: &Report) -> Result<String, Box<dyn std::error::Error>> {
         let mut wtrb = csv::WriterBuilder::new();
         wtrb.has_headers(true);
         let mut wtr = wtrb.from_writer(vec![]);
-        wtr.serialize(&self.to_seralizer())?;
+        wtr.serialize((
+            self.to_seralizer(),
+            SeedSerialize {
+                seed: report.seed(),
+            },
+        ))?;
         return Ok(String::from_utf8(wtr.into_inner()?)?.trim().to_owned());
     }
 }
 
+/// Counts by key, e.g. 'HTTP/1.1:10;HTTP/2:5'
+pub fn count_string(counts: &collections::BTreeMap<String, usize>) -> String {
+    return counts
+        .iter()
+        .map(|(key, count)| format!("{}:{}", key, count))
+        .collect::<Vec<String>>()
+        .join(";");
+}
+
 #[test]
 fn increment_test() {
     let mut state = State::new(4);
@@ -112,6 +557,108 @@ fn done_test() {
     assert!(state.done());
 }
 
+#[test]
+fn outcome_test() {
+    let response = |status| client::Response {
+        status,
+        protocol: "HTTP/2".to_string(),
+        ..Default::default()
+    };
+
+    let request = crate::config::test_config();
+    let outcome = Outcome::new(&request, &Ok(response(204)));
+    assert_eq!((outcome.success, outcome.fail, outcome.error), (1, 0, 0));
+    assert_eq!(outcome.code, 204);
+    assert_eq!(outcome.protocol, "HTTP/2".to_string());
+
+    let outcome = Outcome::new(&request, &Ok(response(404)));
+    assert_eq!((outcome.success, outcome.fail, outcome.error), (0, 1, 0));
+
+    assert_eq!(outcome.label, "GET http://www.example.com".to_string());
+
+    let err = ClientError::RequestError(
+        "Connection Failed".to_string(),
+        "http://www.example.com/: Connection Failed: Connect error".to_string(),
+    );
+    let outcome = Outcome::new(&request, &Err(err));
+    assert_eq!((outcome.success, outcome.fail, outcome.error), (0, 0, 1));
+    assert_eq!(outcome.code, 0);
+    assert_eq!(outcome.error_kind, "Connection Failed".to_string());
+}
+
+#[test]
+fn error_kind_test() {
+    // Nothing's listening once the listener's dropped.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut request = crate::config::test_config();
+    request.endpoint = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    let result = client::Client::new(&request).and_then(|client| client.execute());
+    let outcome = Outcome::new(&request, &result);
+    assert_eq!(outcome.error, 1);
+    assert_eq!(outcome.error_kind, "Connection Failed".to_string());
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("Connection refused"));
+
+    #[cfg(feature = "async")]
+    {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let http = client::http_client(&request).unwrap();
+        let client = client::Client::new(&request).unwrap();
+        let result = runtime.block_on(client.execute_async(&http));
+        let outcome = Outcome::new(&request, &result);
+        assert_eq!(outcome.error_kind, "Connection Failed".to_string());
+    }
+}
+
+#[test]
+fn streaming_test() {
+    let mut state = State::streaming();
+    assert!(!state.done());
+
+    let outcome = Outcome {
+        success: 1,
+        ..Default::default()
+    };
+    state.record(&outcome);
+    state.record(&outcome);
+    assert!(!state.done());
+    assert_eq!(state.requested, 2);
+
+    state.plan(3);
+    assert!(!state.done());
+    state.record(&outcome);
+    assert!(state.done());
+
+    let mut state = State::streaming();
+    state.plan(0);
+    assert!(state.done());
+}
+
+#[test]
+fn protocols_test() {
+    let mut state = State::new(3);
+    for protocol in ["HTTP/2", "HTTP/1.1", "HTTP/2"] {
+        state.record(&Outcome {
+            success: 1,
+            protocol: protocol.to_string(),
+            ..Default::default()
+        });
+    }
+
+    assert_eq!(
+        count_string(&state.protocols),
+        "HTTP/1.1:1;HTTP/2:2".to_string()
+    );
+    assert!(state.string().ends_with(" protocols=HTTP/1.1:1;HTTP/2:2"));
+}
+
 #[test]
 fn string_test() {
     let expected = String::from("requested=4 processed=1 success=1 fail=0 error=0 duration=");
@@ -126,3 +673,151 @@ fn string_test() {
         expected
     );
 }
+
+#[test]
+fn phases_test() {
+    let ms = time::Duration::from_millis;
+    let mut state = State::new(2);
+    for total in [10, 30] {
+        state.record(&Outcome {
+            success: 1,
+            timings: Some(timings::Timings {
+                ttfb: Some(ms(total - 1)),
+                download: Some(ms(1)),
+                total: ms(total),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    }
+    state.record(&Outcome {
+        error: 1,
+        ..Default::default()
+    });
+
+    assert_eq!(state.phases[0].count(), 0); // dns
+    assert_eq!(state.phases[3].count(), 2); // ttfb
+    assert_eq!(state.phases[5].count(), 2); // total
+    assert!((9_900..10_100).contains(&state.phases[5].percentile(50.0)));
+    assert_eq!(state.phases[5].max(), 30_000);
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn to_json_test() {
+    let mut state = State::new(1);
+    state.record(&Outcome {
+        success: 1,
+        timings: Some(timings::Timings {
+            total: time::Duration::from_millis(12),
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+
+    let json: serde_json::Value =
+        serde_json::from_str(&state.to_json(&Report::new(0, None))).unwrap();
+    assert_eq!(json["success"], 1);
+    assert_eq!(json["timings"]["total"]["count"], 1);
+    assert_eq!(json["timings"]["total"]["p99"], 12.0);
+    assert!(json["timings"].get("dns").is_none());
+    assert_eq!(json["latencies"], serde_json::json!([[12.0, 1]]));
+    assert_eq!(json["rows"], serde_json::json!([]));
+    assert!(json.get("seed").is_none());
+
+    let json: serde_json::Value =
+        serde_json::from_str(&state.to_json(&Report::new(0, Some(42)))).unwrap();
+    assert_eq!(json["seed"], 42);
+}
+
+#[test]
+fn csv_test() {
+    let state = State::new(1);
+    let csv = state.to_csv(&Report::new(0, Some(42))).unwrap();
+    let mut lines = csv.lines();
+    assert!(lines.next().unwrap().ends_with(",encodings,seed"));
+    assert!(lines.next().unwrap().ends_with(",42"));
+
+    let csv = state.to_csv(&Report::new(0, None)).unwrap();
+    assert!(csv.lines().nth(1).unwrap().ends_with(","));
+}
+
+#[test]
+fn bytes_test() {
+    let mut state = State::new(2);
+    for _ in 0..2 {
+        state.record(&Outcome {
+            success: 1,
+            bytes_received: 1_500_000,
+            bytes_sent: 100,
+            ..Default::default()
+        });
+    }
+
+    assert_eq!(state.bytes_received, 3_000_000);
+    assert_eq!(state.bytes_sent, 200);
+    assert_eq!(state.throughput(time::Duration::from_secs(2)), 1.5);
+    assert!(state
+        .string()
+        .contains(" received=3000000B sent=200B throughput="));
+}
+
+#[test]
+fn encodings_test() {
+    let mut state = State::new(3);
+    for (encoding, body_size) in [("gzip", 100), ("br", 80), ("gzip", 100)] {
+        state.record(&Outcome {
+            success: 1,
+            encoding: encoding.to_string(),
+            body_size,
+            decoded_size: 1000,
+            ..Default::default()
+        });
+    }
+    state.record(&Outcome {
+        success: 1,
+        body_size: 1000,
+        decoded_size: 1000,
+        ..Default::default()
+    });
+
+    assert_eq!(state.bytes_compressed, 280);
+    assert_eq!(state.bytes_decompressed, 3000);
+    assert!(state
+        .string()
+        .ends_with(" compressed=280B decompressed=3000B encodings=br:1;gzip:2"));
+}
+
+#[test]
+fn interval_test() {
+    let outcome = |success, total| Outcome {
+        success,
+        error: 1 - success,
+        timings: Some(timings::Timings {
+            total: time::Duration::from_millis(total),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let mut state = State::new(3);
+    state.record(&outcome(1, 10));
+    state.record(&outcome(0, 20));
+    let first = state.interval();
+    assert_eq!((first.processed, first.success, first.error), (2, 1, 1));
+    assert_eq!(first.total_processed, 2);
+
+    state.record(&outcome(1, 30));
+    let second = state.interval();
+    assert_eq!((second.processed, second.success, second.error), (1, 1, 0));
+    assert_eq!((second.total_processed, second.total_error), (3, 1));
+    assert!((29.0..31.0).contains(&second.p50));
+    assert!(second
+        .string()
+        .contains(" processed=1 success=1 fail=0 error=0 "));
+
+    let csv = second.to_csv(true).unwrap();
+    assert!(csv.starts_with("elapsed,interval,processed,"));
+    assert_eq!(csv.lines().count(), 2);
+    assert_eq!(second.to_csv(false).unwrap().lines().count(), 1);
+}
@@ -1,9 +1,25 @@
-//! Simple threadpool.
+//! Simple threadpool, with a bounded job queue; 'execute' blocks while the queue is full.
 //! REF: https://web.mit.edu/rust-lang_v1.25/arch/amd64_ubuntu1404/share/doc/rust/html/book/second-edition/ch20-03-designing-the-interface.html
 
+use std::cell;
 use std::sync;
 use std::thread;
 
+thread_local! {
+    /// Index of the pool worker on this thread; 0 off the pool.
+    static WORKER: cell::Cell<usize> = const { cell::Cell::new(0) };
+}
+
+/// Index of the worker the caller's running on.
+pub fn worker() -> usize {
+    return WORKER.with(|worker| worker.get());
+}
+
+/// Marks the calling thread as a worker; also for the async engine's runtime threads.
+pub fn set_worker(id: usize) {
+    WORKER.with(|worker| worker.set(id));
+}
+
 enum Message {
     NewJob(Job),
     Terminate,
@@ -11,7 +27,7 @@ enum Message {
 
 pub struct ThreadPool {
     workers: Vec<Worker>,
-    sender: sync::mpsc::Sender<Message>,
+    sender: sync::mpsc::SyncSender<Message>,
 }
 
 trait FnBox {
@@ -32,14 +48,14 @@ impl ThreadPool {
             size = 1;
         }
 
-        let (sender, receiver) = sync::mpsc::channel();
+        let (sender, receiver) = sync::mpsc::sync_channel(size);
 
         let receiver = sync::Arc::new(sync::Mutex::new(receiver));
 
         let mut workers = Vec::with_capacity(size);
 
-        for _ in 0..size {
-            workers.push(Worker::new(sync::Arc::clone(&receiver)));
+        for id in 0..size {
+            workers.push(Worker::new(id, sync::Arc::clone(&receiver)));
         }
 
         ThreadPool { workers, sender }
@@ -60,16 +76,19 @@ struct Worker {
 }
 
 impl Worker {
-    fn new(receiver: sync::Arc<sync::Mutex<sync::mpsc::Receiver<Message>>>) -> Worker {
-        let thread = thread::spawn(move || loop {
-            let message = receiver.lock().unwrap().recv().unwrap();
-
-            match message {
-                Message::NewJob(job) => {
-                    job.call_box();
-                }
-                Message::Terminate => {
-                    break;
+    fn new(id: usize, receiver: sync::Arc<sync::Mutex<sync::mpsc::Receiver<Message>>>) -> Worker {
+        let thread = thread::spawn(move || {
+            set_worker(id);
+            loop {
+                let message = receiver.lock().unwrap().recv().unwrap();
+
+                match message {
+                    Message::NewJob(job) => {
+                        job.call_box();
+                    }
+                    Message::Terminate => {
+                        break;
+                    }
                 }
             }
         });
@@ -93,3 +112,33 @@ impl Drop for ThreadPool {
         }
     }
 }
+
+#[test]
+fn execute_test() {
+    let (tx, rx) = sync::mpsc::channel();
+    let pool = ThreadPool::new(2);
+
+    for i in 0..10 {
+        let tx = tx.clone();
+        pool.execute(move || tx.send(i).unwrap());
+    }
+    drop(pool);
+
+    let mut got: Vec<usize> = rx.try_iter().collect();
+    got.sort();
+    assert_eq!(got, (0..10).collect::<Vec<usize>>());
+}
+
+#[test]
+fn worker_test() {
+    let (tx, rx) = sync::mpsc::channel();
+    let pool = ThreadPool::new(2);
+
+    for _ in 0..10 {
+        let tx = tx.clone();
+        pool.execute(move || tx.send(worker()).unwrap());
+    }
+    drop(pool);
+
+    assert!(rx.try_iter().all(|id| id < 2));
+}
@@ -0,0 +1,307 @@
+//! Per request phase timings, similar to curl's '-w' timing variables.
+//!
+//! Phases don't overlap: dns, connect (TCP), tls (handshake), ttfb (connection ready to
+//! response headers) and download (response headers to the end of the body); they add up
+//! to total. Phases that didn't happen, e.g. dns and connect on a reused connection, are
+//! None.
+//!
+//! Connection phases are marked by hooks in the resolver and connectors used by 'Client';
+//! thread local for the threads engine and task local for the async engine. The threads
+//! engine can't observe TCP connect for plain http, so it's included in ttfb there; and the
+//! async engine may connect outside of the request's task (e.g. HTTP/2 connections shared
+//! by concurrent requests), leaving those phases None.
+
+use crate::errors::ClientError;
+
+use std::cell;
+use std::io;
+use std::net;
+use std::sync;
+use std::time;
+
+use serde_derive::{Deserialize, Serialize};
+
+pub static PHASES: [&str; 6] = ["dns", "connect", "tls", "ttfb", "download", "total"];
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Timings {
+    pub dns: Option<time::Duration>,
+    pub connect: Option<time::Duration>,
+    pub tls: Option<time::Duration>,
+    pub ttfb: Option<time::Duration>,
+    pub download: Option<time::Duration>,
+    pub total: time::Duration,
+}
+
+impl Timings {
+    /// Works out phases from connection marks and when the request started, received
+    /// headers and finished.
+    pub fn new(
+        marks: &Marks,
+        start: time::Instant,
+        headers: time::Instant,
+        end: time::Instant,
+    ) -> Timings {
+        let span = |from: Option<time::Instant>, to: Option<time::Instant>| match (from, to) {
+            (Some(from), Some(to)) => Some(to.saturating_duration_since(from)),
+            _ => None,
+        };
+
+        let ready = marks
+            .tls_done
+            .or(marks.connected)
+            .or(marks.dns_end)
+            .unwrap_or(start);
+
+        return Timings {
+            dns: span(marks.dns_start, marks.dns_end),
+            connect: span(marks.dns_end, marks.connected),
+            tls: span(marks.connected, marks.tls_done),
+            ttfb: Some(headers.saturating_duration_since(ready)),
+            download: Some(end.saturating_duration_since(headers)),
+            total: end.saturating_duration_since(start),
+        };
+    }
+
+    /// Phases in 'PHASES' order.
+    pub fn phases(&self) -> [Option<time::Duration>; 6] {
+        return [
+            self.dns,
+            self.connect,
+            self.tls,
+            self.ttfb,
+            self.download,
+            Some(self.total),
+        ];
+    }
+
+    /// e.g. 'dns=1.2ms connect=0.3ms tls=- ttfb=10.1ms download=0.2ms total=11.8ms'
+    pub fn string(&self) -> String {
+        return PHASES
+            .iter()
+            .zip(self.phases())
+            .map(|(name, phase)| match phase {
+                Some(phase) => format!("{}={:.1?}", name, phase),
+                None => format!("{}=-", name),
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+    }
+}
+
+/// Connection milestones for the request in flight.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Marks {
+    pub dns_start: Option<time::Instant>,
+    pub dns_end: Option<time::Instant>,
+    pub connected: Option<time::Instant>,
+    pub tls_done: Option<time::Instant>,
+}
+
+thread_local! {
+    static MARKS: cell::RefCell<Marks> = cell::RefCell::new(Marks::default());
+}
+
+#[cfg(feature = "async")]
+tokio::task_local! {
+    static TASK_MARKS: cell::RefCell<Marks>;
+}
+
+/// Clears this thread's marks, ahead of a request on the threads engine.
+pub fn reset() {
+    MARKS.with(|m| *m.borrow_mut() = Marks::default());
+}
+
+/// This thread's marks, after a request on the threads engine.
+pub fn marks() -> Marks {
+    return MARKS.with(|m| *m.borrow());
+}
+
+fn mark(f: &dyn Fn(&mut Marks)) {
+    #[cfg(feature = "async")]
+    if TASK_MARKS.try_with(|m| f(&mut m.borrow_mut())).is_ok() {
+        return;
+    }
+
+    MARKS.with(|m| f(&mut m.borrow_mut()));
+}
+
+/// Runs 'future' with its own marks, returning them along with its output.
+#[cfg(feature = "async")]
+pub async fn scope<F: std::future::Future>(future: F) -> (F::Output, Marks) {
+    return TASK_MARKS
+        .scope(cell::RefCell::new(Marks::default()), async {
+            let output = future.await;
+            (output, TASK_MARKS.with(|m| *m.borrow()))
+        })
+        .await;
+}
+
+/// Resolver for the threads engine, marking dns.
+pub struct Resolver;
+
+impl ureq::Resolver for Resolver {
+    fn resolve(&self, netloc: &str) -> io::Result<Vec<net::SocketAddr>> {
+        mark(&|m| m.dns_start = Some(time::Instant::now()));
+        let addrs = net::ToSocketAddrs::to_socket_addrs(netloc).map(|a| a.collect());
+        mark(&|m| m.dns_end = Some(time::Instant::now()));
+        return addrs;
+    }
+}
+
+/// TLS connector for the threads engine; it's handed an already connected socket, so it
+/// marks both connected and the end of the handshake.
+pub struct TlsConnector(native_tls::TlsConnector);
+
+impl TlsConnector {
+    pub fn shared() -> Result<sync::Arc<TlsConnector>, ClientError> {
+        static CONNECTOR: sync::OnceLock<sync::Arc<TlsConnector>> = sync::OnceLock::new();
+        if let Some(connector) = CONNECTOR.get() {
+            return Ok(connector.clone());
+        }
+
+        let connector = match native_tls::TlsConnector::new() {
+            Ok(connector) => sync::Arc::new(TlsConnector(connector)),
+            Err(err) => return Err(ClientError::HTTPError(err.to_string())),
+        };
+        return Ok(CONNECTOR.get_or_init(|| connector).clone());
+    }
+}
+
+impl ureq::TlsConnector for TlsConnector {
+    fn connect(
+        &self,
+        dns_name: &str,
+        io: Box<dyn ureq::ReadWrite>,
+    ) -> Result<Box<dyn ureq::ReadWrite>, ureq::Error> {
+        mark(&|m| m.connected = Some(time::Instant::now()));
+        let stream = ureq::TlsConnector::connect(&self.0, dns_name, io);
+        mark(&|m| m.tls_done = Some(time::Instant::now()));
+        return stream;
+    }
+}
+
+#[cfg(feature = "async")]
+pub use self::asynchronous::{Connector, Resolver as AsyncResolver};
+
+#[cfg(feature = "async")]
+mod asynchronous {
+    use super::mark;
+
+    use hyper_util::client::legacy::connect::dns;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task;
+    use std::time;
+
+    type BoxFuture<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send>>;
+
+    /// Resolver for the async engine, marking dns.
+    #[derive(Clone)]
+    pub struct Resolver(pub dns::GaiResolver);
+
+    impl tower_service::Service<dns::Name> for Resolver {
+        type Response = dns::GaiAddrs;
+        type Error = std::io::Error;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(
+            &mut self,
+            cx: &mut task::Context<'_>,
+        ) -> task::Poll<Result<(), Self::Error>> {
+            return self.0.poll_ready(cx);
+        }
+
+        fn call(&mut self, name: dns::Name) -> Self::Future {
+            mark(&|m| m.dns_start = Some(time::Instant::now()));
+            let resolving = self.0.call(name);
+            return Box::pin(async move {
+                let addrs = resolving.await;
+                mark(&|m| m.dns_end = Some(time::Instant::now()));
+                addrs
+            });
+        }
+    }
+
+    /// Wraps a connector, marking when it's done; the end of the TCP connect, or with
+    /// 'tls' the end of the handshake for https.
+    #[derive(Clone)]
+    pub struct Connector<C> {
+        pub inner: C,
+        pub tls: bool,
+    }
+
+    impl<C> tower_service::Service<hyper::Uri> for Connector<C>
+    where
+        C: tower_service::Service<hyper::Uri> + Send,
+        C::Future: Send + 'static,
+    {
+        type Response = C::Response;
+        type Error = C::Error;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(
+            &mut self,
+            cx: &mut task::Context<'_>,
+        ) -> task::Poll<Result<(), Self::Error>> {
+            return self.inner.poll_ready(cx);
+        }
+
+        fn call(&mut self, uri: hyper::Uri) -> Self::Future {
+            let tls = self.tls;
+            let https = uri.scheme_str() == Some("https");
+            let connecting = self.inner.call(uri);
+            return Box::pin(async move {
+                let stream = connecting.await;
+                let now = Some(time::Instant::now());
+                if !tls {
+                    mark(&|m| m.connected = now);
+                } else if https {
+                    mark(&|m| m.tls_done = now);
+                }
+                stream
+            });
+        }
+    }
+}
+
+#[test]
+fn timings_test() {
+    let start = time::Instant::now();
+    let at = |ms| start + time::Duration::from_millis(ms);
+
+    let marks = Marks {
+        dns_start: Some(at(0)),
+        dns_end: Some(at(2)),
+        connected: Some(at(5)),
+        tls_done: Some(at(15)),
+    };
+    let t = Timings::new(&marks, start, at(40), at(45));
+    assert_eq!(t.dns, Some(time::Duration::from_millis(2)));
+    assert_eq!(t.connect, Some(time::Duration::from_millis(3)));
+    assert_eq!(t.tls, Some(time::Duration::from_millis(10)));
+    assert_eq!(t.ttfb, Some(time::Duration::from_millis(25)));
+    assert_eq!(t.download, Some(time::Duration::from_millis(5)));
+    assert_eq!(t.total, time::Duration::from_millis(45));
+
+    // reused connection
+    let t = Timings::new(&Marks::default(), start, at(10), at(12));
+    assert_eq!(t.dns, None);
+    assert_eq!(t.connect, None);
+    assert_eq!(t.ttfb, Some(time::Duration::from_millis(10)));
+    assert_eq!(
+        t.string(),
+        "dns=- connect=- tls=- ttfb=10.0ms download=2.0ms total=12.0ms".to_string()
+    );
+}
+
+#[test]
+fn marks_test() {
+    reset();
+    mark(&|m| m.connected = Some(time::Instant::now()));
+    assert!(marks().connected.is_some());
+    assert!(marks().dns_end.is_none());
+
+    reset();
+    assert!(marks().connected.is_none());
+}
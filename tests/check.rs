@@ -0,0 +1,29 @@
+//! Runs the built binary, for what's only seen on its output.
+
+#[cfg(feature = "json")]
+#[test]
+fn check_json_output_test() {
+    // Nothing's listening once the listener's dropped, so requests fail fast.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}/", listener.local_addr().unwrap());
+    drop(listener);
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_noop-client"))
+        .args([
+            "--check",
+            "--output=json",
+            "-n",
+            "2",
+            "--endpoint",
+            &endpoint,
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // Only the summary; counts from checking don't belong on stdout.
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let summary: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(summary["requested"], 2);
+    assert_eq!(summary["error"], 2);
+}